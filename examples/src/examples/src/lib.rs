@@ -58,8 +58,9 @@ async fn find_total_transfer_amount_between(from: u64, to: u64) -> WrappedU256 {
         .event_transfer(from, to, CallOptions::default())
         .await
         .unwrap()
-        .iter()
-        .map(|(_, v)| {
+        .events
+        .values()
+        .map(|v| {
             v.iter().map(|log| {
                 log.event
                     .params
@@ -1,5 +1,7 @@
 use ic_web3_rs::ethabi::param_type::ParamType;
 use ic_web3_rs::ethabi::{Event, Function, StateMutability};
+use inflector::cases::pascalcase::to_pascal_case;
+use inflector::cases::screamingsnakecase::to_screaming_snake_case;
 use inflector::cases::snakecase::to_snake_case;
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::ToTokens as _;
@@ -39,17 +41,24 @@ pub fn abi_from_file(path: impl AsRef<Path>) -> TokenStream {
 
     let struct_name = ident(name.clone());
     let static_caller = format_ident!("{}StaticCaller", name);
+    let call_enum = format_ident!("{}Call", name);
 
     let mut send_fns = Vec::new();
     let mut call_fns = Vec::new();
     let mut static_call_fns = Vec::new();
     let mut get_logs_fns: Vec<TokenStream> = Vec::new();
+    let mut encode_fns = Vec::new();
+    let mut prepare_fns = Vec::new();
 
     for f in abis.functions() {
+        encode_fns.push(encode_fn_from_abi(&call_enum, f));
         let method = method(f);
         let f_token = fn_from_abi(f);
         match method {
-            Method::Call => call_fns.push(f_token),
+            Method::Call => {
+                call_fns.push(f_token);
+                prepare_fns.push(prepare_fn_from_abi(&call_enum, f));
+            }
             Method::Send => {
                 send_fns.push(f_token);
 
@@ -66,6 +75,8 @@ pub fn abi_from_file(path: impl AsRef<Path>) -> TokenStream {
         get_logs_fns.push(f);
     }
 
+    let call_enum_def = call_enum_from_abi(&call_enum, abis.functions());
+
     quote! {
         // "hygenic" ident for generic
         pub struct #struct_name<SolidityBindgenProvider> {
@@ -102,6 +113,12 @@ pub fn abi_from_file(path: impl AsRef<Path>) -> TokenStream {
             }
         }
 
+        impl<SolidityBindgenProvider> #struct_name<SolidityBindgenProvider> {
+            #(#encode_fns)*
+
+            #(#prepare_fns)*
+        }
+
         impl<SolidityBindgenProvider> #struct_name<SolidityBindgenProvider> where SolidityBindgenProvider: ::ic_solidity_bindgen::SendProvider {
 
             // TODO: This API is not in the spirit of this library
@@ -147,6 +164,8 @@ pub fn abi_from_file(path: impl AsRef<Path>) -> TokenStream {
         {
             #(#static_call_fns)*
         }
+
+        #call_enum_def
     }
 }
 
@@ -203,10 +222,7 @@ fn param_type(kind: &ParamType) -> (TokenStream, usize) {
         ParamType::Tuple(members) => match members.len() {
             0 => (quote! { ::ic_solidity_bindgen::internal::Empty }, 1),
             _ => {
-                let members: Vec<_> = members
-                    .into_iter()
-                    .map(|member| param_type(member))
-                    .collect();
+                let members: Vec<_> = members.iter().map(param_type).collect();
                 // Unwrap is ok because in this branch there must be at least 1 item.
                 let nesting = 1 + members.iter().map(|(_, n)| *n).max().unwrap();
                 let types = members.iter().map(|(ty, _)| ty);
@@ -217,7 +233,7 @@ fn param_type(kind: &ParamType) -> (TokenStream, usize) {
 }
 
 pub fn to_rust_name(type_name: &str, eth_name: &str, i: usize) -> String {
-    if eth_name == "" {
+    if eth_name.is_empty() {
         format!("{}_{}", type_name, i)
     } else {
         to_snake_case(eth_name)
@@ -231,13 +247,33 @@ pub fn event_from_abi(event: &Event) -> TokenStream {
         format!("event_{}", eth_name).as_str(),
         0,
     ));
+    if event.anonymous {
+        // There is no signature topic for anonymous events, so the caller
+        // filters on the indexed inputs instead.
+        return quote! {
+        pub async fn #rust_name(
+            &self,
+            from: u64,
+            to: u64,
+            topics: ::ic_web3_rs::ethabi::TopicFilter,
+            options: ::ic_web3_rs::transports::ic_http_client::CallOptions,
+        ) -> Result<::ic_solidity_bindgen::types::EventLogs, ::ic_web3_rs::Error> {
+            self.provider.find_anonymous(
+                #eth_name,
+                from,
+                to,
+                topics,
+                options
+            ).await
+        }};
+    }
     quote! {
     pub async fn #rust_name(
         &self,
         from: u64,
         to: u64,
         options: ::ic_web3_rs::transports::ic_http_client::CallOptions,
-    ) -> Result<std::collections::HashMap<u64, std::vec::Vec<ic_solidity_bindgen::types::EventLog>>, ::ic_web3_rs::Error> {
+    ) -> Result<::ic_solidity_bindgen::types::EventLogs, ::ic_web3_rs::Error> {
         self.provider.find(
             #eth_name,
             from,
@@ -247,47 +283,311 @@ pub fn event_from_abi(event: &Event) -> TokenStream {
     }}
 }
 
-pub fn fn_from_abi(function: &Function) -> TokenStream {
-    let eth_name = &function.name;
-    let rust_name = ident(to_rust_name("function", eth_name, 0));
+/// The tokens which construct `kind` at runtime.
+fn param_type_value(kind: &ParamType) -> TokenStream {
+    let path = quote! { ::ic_web3_rs::ethabi::ParamType };
+    match kind {
+        ParamType::Address => quote! { #path::Address },
+        ParamType::Bytes => quote! { #path::Bytes },
+        ParamType::Int(size) => quote! { #path::Int(#size) },
+        ParamType::Uint(size) => quote! { #path::Uint(#size) },
+        ParamType::Bool => quote! { #path::Bool },
+        ParamType::String => quote! { #path::String },
+        ParamType::Array(inner) => {
+            let inner = param_type_value(inner);
+            quote! { #path::Array(::std::boxed::Box::new(#inner)) }
+        }
+        ParamType::FixedBytes(len) => quote! { #path::FixedBytes(#len) },
+        ParamType::FixedArray(inner, len) => {
+            let inner = param_type_value(inner);
+            quote! { #path::FixedArray(::std::boxed::Box::new(#inner), #len) }
+        }
+        ParamType::Tuple(members) => {
+            let members = members.iter().map(param_type_value);
+            quote! { #path::Tuple(::std::vec![#(#members),*]) }
+        }
+    }
+}
 
-    // Get the types and names of parameters
-    let params_nesting = if function.inputs.len() > 1 { 1 } else { 0 };
-    let params_in = function.inputs.iter().enumerate().map(|(i, param)| {
-        let name = ident(to_rust_name("input", &param.name, i));
-        let (t, nesting) = param_type(&param.kind);
-
-        // We have to have a branch here because Tokenize isn't implemented for
-        // nested tuples. This is because the impls of Tokenize for (A, B, ..)
-        // require the members to implement Tokenizable instead of Tokenize.
-        // Even if this did compile, it doesn't seem ethabi is architected in a
-        // way to deal with this properly considering the separation between
-        // dynamic and static types, and there are some issues like this one:
-        // https://github.com/openethereum/ethabi/issues/178
-        // Changing this type to Unimplemented always reduces the amount of
-        // nesting to 1 or 0 which compiles.
-        if nesting + params_nesting > 1 {
+fn is_unimplemented(t: &TokenStream) -> bool {
+    t.to_string().contains("Unimplemented")
+}
+
+fn selector_ident(function: &Function) -> Ident {
+    ident(format!(
+        "{}_SELECTOR",
+        to_screaming_snake_case(&function.name)
+    ))
+}
+
+/// The `<Contract>Call` enum, with a variant for the inputs of each function,
+/// the selector constants and the functions to encode and decode calldata.
+fn call_enum_from_abi<'a>(
+    call_enum: &Ident,
+    functions: impl Iterator<Item = &'a Function>,
+) -> TokenStream {
+    let mut variants = Vec::new();
+    let mut consts = Vec::new();
+    let mut encode_fns = Vec::new();
+    let mut encode_arms = Vec::new();
+    let mut decode_arms = Vec::new();
+
+    for function in functions {
+        let variant = ident(to_pascal_case(&function.name));
+        let selector = selector_ident(function);
+        let selector_bytes = function.short_signature();
+        let encode_fn = format_ident!("encode_{}", to_rust_name("function", &function.name, 0));
+        let eth_name = &function.name;
+        let inputs = inputs(function);
+        let names = inputs.iter().map(|(name, _)| name).collect::<Vec<_>>();
+        let types = inputs.iter().map(|(_, t)| t).collect::<Vec<_>>();
+        let params = params(function);
+        let kinds = function
+            .inputs
+            .iter()
+            .map(|param| param_type_value(&param.kind))
+            .collect::<Vec<_>>();
+        let struct_input = is_struct_input(function);
+
+        variants.push(if inputs.is_empty() {
+            quote! { #variant }
+        } else {
+            quote! { #variant { #(#names: #types),* } }
+        });
+        consts.push(quote! {
+            pub const #selector: [u8; 4] = [#(#selector_bytes),*];
+        });
+        // `Tokenize` flattens a struct into its members, so the only input
+        // of a function taking a struct has to be wrapped again.
+        let tokens = if struct_input {
+            quote! {
+                ::std::vec![::ic_web3_rs::ethabi::Token::Tuple(
+                    ::ic_web3_rs::contract::tokens::Tokenize::into_tokens(#params),
+                )]
+            }
+        } else {
+            quote! { ::ic_web3_rs::contract::tokens::Tokenize::into_tokens(#params) }
+        };
+        encode_fns.push(quote! {
+            pub fn #encode_fn(#(#names: #types),*) -> ::std::result::Result<::std::vec::Vec<u8>, ::ic_web3_rs::contract::Error> {
+                let tokens = #tokens;
+                if !::ic_web3_rs::ethabi::Token::types_check(&tokens, &[#(#kinds),*]) {
+                    return Err(::ic_web3_rs::ethabi::Error::InvalidData.into());
+                }
+                let mut data = Self::#selector.to_vec();
+                data.extend(::ic_web3_rs::ethabi::encode(&tokens));
+                Ok(data)
+            }
+        });
+        encode_arms.push(quote! {
+            Self::#variant { #(#names),* } => Self::#encode_fn(#(::std::clone::Clone::clone(#names)),*),
+        });
+
+        let decode = if inputs.is_empty() {
+            quote! { Ok(Self::#variant) }
+        } else if types.iter().any(|t| is_unimplemented(t)) {
             quote! {
-                #name: ::ic_solidity_bindgen::internal::Unimplemented
+                Err(::ic_web3_rs::contract::Error::InvalidOutputType(
+                    ::std::format!("the inputs of {} are not supported", #eth_name),
+                ))
             }
         } else {
+            let unwrap_struct = if struct_input {
+                quote! {
+                    let tokens = match <[::ic_web3_rs::ethabi::Token; 1] as ::std::convert::TryFrom<_>>::try_from(tokens) {
+                        Ok([::ic_web3_rs::ethabi::Token::Tuple(members)]) => members,
+                        _ => return Err(::ic_web3_rs::ethabi::Error::InvalidData.into()),
+                    };
+                }
+            } else {
+                quote! {}
+            };
+            let decoded = if inputs.len() == 1 {
+                quote! { #(#types)* }
+            } else {
+                quote! { (#(#types,)*) }
+            };
+            let bindings = if inputs.len() == 1 {
+                quote! { #(#names)* }
+            } else {
+                quote! { (#(#names,)*) }
+            };
             quote! {
-                #name: #t
+                let tokens = ::ic_web3_rs::ethabi::decode(&[#(#kinds),*], data)?;
+                #unwrap_struct
+                let #bindings = <#decoded as ::ic_web3_rs::contract::tokens::Detokenize>::from_tokens(tokens)?;
+                Ok(Self::#variant { #(#names),* })
+            }
+        };
+        decode_arms.push(quote! {
+            if selector == Self::#selector {
+                return { #decode };
+            }
+        });
+    }
+
+    quote! {
+        /// The input of a call to each function of the contract.
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum #call_enum {
+            #(#variants,)*
+        }
+
+        impl #call_enum {
+            #(#consts)*
+
+            #(#encode_fns)*
+
+            /// The calldata of this call, starting with the function selector.
+            pub fn encode(&self) -> ::std::result::Result<::std::vec::Vec<u8>, ::ic_web3_rs::contract::Error> {
+                match self {
+                    #(#encode_arms)*
+                }
+            }
+
+            /// Decodes calldata, such as the input of a transaction to the contract.
+            pub fn decode(calldata: &[u8]) -> ::std::result::Result<Self, ::ic_web3_rs::contract::Error> {
+                if calldata.len() < 4 {
+                    return Err(::ic_web3_rs::ethabi::Error::InvalidData.into());
+                }
+                let (selector, data) = calldata.split_at(4);
+                #(#decode_arms)*
+                Err(::ic_web3_rs::ethabi::Error::InvalidData.into())
             }
         }
-    });
+    }
+}
+
+/// `encode_<fn>` on the contract, which returns the calldata of a call.
+fn encode_fn_from_abi(call_enum: &Ident, function: &Function) -> TokenStream {
+    let encode_fn = format_ident!("encode_{}", to_rust_name("function", &function.name, 0));
+    let inputs = inputs(function);
+    let names = inputs.iter().map(|(name, _)| name).collect::<Vec<_>>();
+    let types = inputs.iter().map(|(_, t)| t);
+    quote! {
+        pub fn #encode_fn(&self, #(#names: #types),*) -> ::std::result::Result<::std::vec::Vec<u8>, ::ic_web3_rs::contract::Error> {
+            #call_enum::#encode_fn(#(#names),*)
+        }
+    }
+}
+
+/// The Rust names and types of the inputs of `function`.
+fn inputs(function: &Function) -> Vec<(Ident, TokenStream)> {
+    let params_nesting = if function.inputs.len() > 1 { 1 } else { 0 };
+    function
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(i, param)| {
+            let name = ident(to_rust_name("input", &param.name, i));
+            let (t, nesting) = param_type(&param.kind);
+
+            // We have to have a branch here because Tokenize isn't implemented for
+            // nested tuples. This is because the impls of Tokenize for (A, B, ..)
+            // require the members to implement Tokenizable instead of Tokenize.
+            // Even if this did compile, it doesn't seem ethabi is architected in a
+            // way to deal with this properly considering the separation between
+            // dynamic and static types, and there are some issues like this one:
+            // https://github.com/openethereum/ethabi/issues/178
+            // Changing this type to Unimplemented always reduces the amount of
+            // nesting to 1 or 0 which compiles.
+            if nesting + params_nesting > 1 {
+                (
+                    name,
+                    quote! { ::ic_solidity_bindgen::internal::Unimplemented },
+                )
+            } else {
+                (name, t)
+            }
+        })
+        .collect()
+}
+
+/// Whether the only input of `function` is a struct, which `Tokenize`
+/// flattens into the tokens of its members.
+fn is_struct_input(function: &Function) -> bool {
+    match function.inputs.as_slice() {
+        [param] => matches!(&param.kind, ParamType::Tuple(members) if !members.is_empty()),
+        _ => false,
+    }
+}
 
+/// The inputs of `function` as the value passed to `Tokenize`: the input
+/// itself if there is one, a tuple otherwise.
+fn params(function: &Function) -> TokenStream {
     let params = function
         .inputs
         .iter()
         .enumerate()
         .map(|(i, param)| ident(to_rust_name("input", &param.name, i)).into_token_stream());
 
-    let params = if function.inputs.len() == 1 {
+    if function.inputs.len() == 1 {
         quote! { #(#params)* }
     } else {
         quote! { (#(#params),*) }
-    };
+    }
+}
+
+/// The type a call to `function` is decoded into.
+fn call_output(function: &Function) -> TokenStream {
+    match function.outputs.len() {
+        0 => quote! { ::ic_solidity_bindgen::internal::Empty },
+        1 => {
+            let (t, nesting) = param_type(&function.outputs[0].kind);
+            if nesting < 2 {
+                t
+            } else {
+                quote! {
+                    ::ic_solidity_bindgen::internal::Unimplemented
+                }
+            }
+        }
+        _ => {
+            let types = function.outputs.iter().map(|o| {
+                let (t, nesting) = param_type(&o.kind);
+                if nesting != 0 {
+                    quote! {
+                        ::ic_solidity_bindgen::internal::Unimplemented
+                    }
+                } else {
+                    t
+                }
+            });
+
+            quote! { (#(#types),*) }
+        }
+    }
+}
+
+/// `prepare_<fn>` on the contract, which returns the call for batching with
+/// Multicall3.
+fn prepare_fn_from_abi(call_enum: &Ident, function: &Function) -> TokenStream {
+    let prepare_fn = format_ident!("prepare_{}", to_rust_name("function", &function.name, 0));
+    let encode_fn = format_ident!("encode_{}", to_rust_name("function", &function.name, 0));
+    let inputs = inputs(function);
+    let names = inputs.iter().map(|(name, _)| name).collect::<Vec<_>>();
+    let types = inputs.iter().map(|(_, t)| t);
+    let output = call_output(function);
+    let outputs = function.outputs.iter().map(|o| param_type_value(&o.kind));
+    quote! {
+        pub fn #prepare_fn(&self, #(#names: #types),*) -> ::std::result::Result<::ic_solidity_bindgen::multicall::PreparedCall<#output>, ::ic_web3_rs::contract::Error> {
+            Ok(::ic_solidity_bindgen::multicall::PreparedCall::new(
+                self.address,
+                #call_enum::#encode_fn(#(#names),*)?,
+                ::std::vec![#(#outputs),*],
+            ))
+        }
+    }
+}
+
+pub fn fn_from_abi(function: &Function) -> TokenStream {
+    let eth_name = &function.name;
+    let rust_name = ident(to_rust_name("function", eth_name, 0));
+
+    let params_in = inputs(function)
+        .into_iter()
+        .map(|(name, t)| quote! { #name: #t });
+    let params = params(function);
 
     let method = method(function);
 
@@ -302,46 +602,48 @@ pub fn fn_from_abi(function: &Function) -> TokenStream {
         // to make further queries for data.
         quote! { SolidityBindgenProvider::Out }
     } else {
-        match function.outputs.len() {
-            0 => quote! { ::ic_solidity_bindgen::internal::Empty },
-            1 => {
-                let (t, nesting) = param_type(&function.outputs[0].kind);
-                if nesting < 2 {
-                    t
-                } else {
-                    quote! {
-                        ::ic_solidity_bindgen::internal::Unimplemented
-                    }
-                }
-            }
-            _ => {
-                let types = function.outputs.iter().map(|o| {
-                    let (t, nesting) = param_type(&o.kind);
-                    if nesting != 0 {
-                        quote! {
-                            ::ic_solidity_bindgen::internal::Unimplemented
-                        }
-                    } else {
-                        t
-                    }
-                });
+        call_output(function)
+    };
 
-                quote! { (#(#types),*) }
-            }
-        }
+    let options = ident("options");
+    let options_type = quote! { Option<::ic_web3_rs::contract::Options> };
+    let options_param = quote! { #options: #options_type };
+
+    // Payable functions take the value to send. Others refuse a value in the
+    // options, as the call would revert.
+    let payable = function.state_mutability == StateMutability::Payable;
+    let value = value_ident(function);
+    let value_param = if method == Method::Send && payable {
+        Some(quote! { #value: ::ic_web3_rs::types::U256, })
+    } else {
+        None
     };
 
     let fn_call = match method {
         Method::Call => quote! { self.provider.call(#eth_name, #params).await },
-        Method::Send => quote! { self.provider.send(#eth_name, #params, options).await },
+        Method::Send if payable => quote! {
+            let #options = ::ic_solidity_bindgen::internal::payable_options(#options, #value);
+            self.provider.send(#eth_name, #params, #options).await
+        },
+        Method::Send => quote! {
+            let #options = ::ic_solidity_bindgen::internal::non_payable_options(#eth_name, #options)?;
+            self.provider.send(#eth_name, #params, #options).await
+        },
     };
-    let options = ident("options");
-    let options_type = quote! { Option<::ic_web3_rs::contract::Options> };
-    let options_param = quote! { #options: #options_type };
 
     quote! {
-        pub async fn #rust_name(&self, #(#params_in,)* #options_param) -> ::std::result::Result<#ok, ::ic_web3_rs::Error>  {
+        pub async fn #rust_name(&self, #(#params_in,)* #value_param #options_param) -> ::std::result::Result<#ok, ::ic_web3_rs::Error>  {
             #fn_call
         }
     }
 }
+
+/// `value`, unless the function has an input of that name.
+fn value_ident(function: &Function) -> Ident {
+    let taken = function
+        .inputs
+        .iter()
+        .enumerate()
+        .any(|(i, param)| to_rust_name("input", &param.name, i) == "value");
+    ident(if taken { "msg_value" } else { "value" })
+}
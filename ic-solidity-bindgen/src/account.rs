@@ -0,0 +1,116 @@
+//! Native transfers, raw calldata and account queries, which don't need a
+//! contract binding.
+
+use crate::context::Web3Context;
+use ic_web3_rs::{
+    contract::Options,
+    transports::ic_http_client::CallOptions,
+    types::{Address, Block, BlockId, BlockNumber, Bytes, H256, U256},
+};
+
+impl Web3Context {
+    /// Sends `value` wei to `to`. Fees, nonce and gas are filled in like for
+    /// contract calls when `options` doesn't set them, and `options.value` is
+    /// replaced by `value`.
+    pub async fn transfer(
+        &self,
+        to: Address,
+        value: U256,
+        options: Option<Options>,
+    ) -> Result<(H256, Option<ic_web3_rs::Error>), ic_web3_rs::Error> {
+        self.send_raw(to, vec![], value, options).await
+    }
+
+    /// Sends a transaction with arbitrary calldata, for example a payload
+    /// which was encoded elsewhere. Otherwise like [`Web3Context::transfer`].
+    pub async fn send_raw(
+        &self,
+        to: Address,
+        data: Vec<u8>,
+        value: U256,
+        options: Option<Options>,
+    ) -> Result<(H256, Option<ic_web3_rs::Error>), ic_web3_rs::Error> {
+        let call_options = options
+            .as_ref()
+            .and_then(|o| o.call_options.clone())
+            .unwrap_or_else(|| self.call_options());
+        let options = Options {
+            value: Some(value),
+            ..self.tx_options(options).await?
+        };
+        if self.is_dry_run() {
+            return Ok(self.dry_run(to, data, options).await);
+        }
+        let signed = self
+            .sign_transaction(
+                to,
+                data,
+                Options {
+                    call_options: None,
+                    ..options
+                },
+            )
+            .await?;
+        let res = self.send_raw_transaction(&signed, call_options).await;
+        Ok((signed.transaction_hash, res.err()))
+    }
+
+    /// Calls `to` with arbitrary calldata and returns the undecoded output.
+    /// If the call reverts, the error contains the decoded revert reason.
+    pub async fn call_raw(
+        &self,
+        to: Address,
+        data: Vec<u8>,
+        value: U256,
+        call_options: CallOptions,
+    ) -> Result<Bytes, ic_web3_rs::Error> {
+        let options = Options {
+            value: Some(value),
+            ..Default::default()
+        };
+        self.simulate(to, &data, &options, call_options).await
+    }
+
+    /// The balance of `address` in wei, at the latest block unless `block` is given.
+    pub async fn balance(
+        &self,
+        address: Address,
+        block: Option<BlockNumber>,
+        call_options: CallOptions,
+    ) -> Result<U256, ic_web3_rs::Error> {
+        self.eth().balance(address, block, call_options).await
+    }
+
+    /// The number of transactions sent by `address`. With
+    /// `BlockNumber::Pending`, this is the nonce of its next transaction.
+    pub async fn nonce(
+        &self,
+        address: Address,
+        block: Option<BlockNumber>,
+        call_options: CallOptions,
+    ) -> Result<U256, ic_web3_rs::Error> {
+        self.eth()
+            .transaction_count(address, block, call_options)
+            .await
+    }
+
+    /// The code deployed at `address`, empty for accounts without code.
+    pub async fn code(
+        &self,
+        address: Address,
+        block: Option<BlockNumber>,
+        call_options: CallOptions,
+    ) -> Result<Bytes, ic_web3_rs::Error> {
+        self.eth().code(address, block, call_options).await
+    }
+
+    pub async fn latest_block(
+        &self,
+        call_options: CallOptions,
+    ) -> Result<Block<H256>, ic_web3_rs::Error> {
+        self.eth()
+            .block(BlockId::Number(BlockNumber::Latest), call_options)
+            .await?
+            .ok_or_else(|| ic_web3_rs::Error::InvalidResponse("No block returned".to_string()))
+    }
+}
@@ -0,0 +1,201 @@
+//! Typed JSON-RPC batches, which send many requests in one HTTP outcall.
+//!
+//! ```ignore
+//! let mut batch = context.batch();
+//! let supply = batch.call(token.prepare_total_supply()?, BlockNumber::Latest);
+//! let balance = batch.balance(owner, BlockNumber::Latest);
+//! let block = batch.block(BlockNumber::Latest);
+//! let mut results = batch.execute().await?;
+//! let supply: U256 = results.take(&supply)?;
+//! ```
+
+use crate::{
+    context::Web3Context,
+    multicall::PreparedCall,
+    revert::with_revert_reason,
+    rpc_methods::{EVMRpcMethod, EVMRpcResponse},
+};
+use ic_web3_rs::{
+    contract::{tokens::Detokenize, Options},
+    ethabi,
+    types::{
+        Address, Block, BlockNumber, Bytes, Filter, Log, Proof, TransactionReceipt, H256, U256, U64,
+    },
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+type Decode<T> = Box<dyn Fn(Value) -> Result<T, ic_web3_rs::Error>>;
+
+/// Identifies the result of a request added to an [`RpcBatch`], and decodes it.
+pub struct BatchHandle<T> {
+    index: usize,
+    decode: Decode<T>,
+}
+
+pub struct RpcBatch {
+    context: Web3Context,
+    methods: Vec<EVMRpcMethod>,
+}
+
+impl Web3Context {
+    /// Starts a JSON-RPC batch. Calls in the batch are made from the context's
+    /// address.
+    pub fn batch(&self) -> RpcBatch {
+        RpcBatch {
+            context: self.clone(),
+            methods: vec![],
+        }
+    }
+}
+
+impl RpcBatch {
+    /// Adds any request. Its result is deserialized into `T`.
+    pub fn add<T: DeserializeOwned + 'static>(&mut self, method: EVMRpcMethod) -> BatchHandle<T> {
+        self.add_with(
+            method,
+            Box::new(|value| serde_json::from_value(value).map_err(Into::into)),
+        )
+    }
+
+    /// Adds any request, decoded by [`EVMRpcMethod::decode`].
+    pub fn request(&mut self, method: EVMRpcMethod) -> BatchHandle<EVMRpcResponse> {
+        let decoder = method.clone();
+        self.add_with(method, Box::new(move |value| decoder.decode(value)))
+    }
+
+    /// Adds an `eth_call` of a generated contract function, as returned by its
+    /// `prepare_<fn>` method.
+    pub fn call<O: Detokenize + 'static>(
+        &mut self,
+        call: PreparedCall<O>,
+        block: BlockNumber,
+    ) -> BatchHandle<O> {
+        let request = self
+            .context
+            .call_request(call.target, &call.calldata, &Options::default());
+        let outputs = call.outputs;
+        self.add_with(
+            EVMRpcMethod::Call(Box::new(request), block),
+            Box::new(move |value| {
+                let data: Bytes = serde_json::from_value(value)?;
+                let tokens = ethabi::decode(&outputs, &data.0)
+                    .map_err(|e| ic_web3_rs::Error::Decoder(e.to_string()))?;
+                O::from_tokens(tokens).map_err(|e| ic_web3_rs::Error::Decoder(e.to_string()))
+            }),
+        )
+    }
+
+    pub fn balance(&mut self, address: Address, block: BlockNumber) -> BatchHandle<U256> {
+        self.add(EVMRpcMethod::Balance(address, block))
+    }
+
+    /// The block with the hashes of its transactions, `None` if it doesn't exist yet.
+    pub fn block(&mut self, block: BlockNumber) -> BatchHandle<Option<Block<H256>>> {
+        self.add(EVMRpcMethod::BlockByNumber(block, false))
+    }
+
+    pub fn transaction_receipt(&mut self, hash: H256) -> BatchHandle<Option<TransactionReceipt>> {
+        self.add(EVMRpcMethod::TransactionReceipt(hash))
+    }
+
+    pub fn block_number(&mut self) -> BatchHandle<U64> {
+        self.add(EVMRpcMethod::BlockNumber)
+    }
+
+    pub fn chain_id(&mut self) -> BatchHandle<U256> {
+        self.add(EVMRpcMethod::ChainId)
+    }
+
+    pub fn logs(&mut self, filter: Filter) -> BatchHandle<Vec<Log>> {
+        self.add(EVMRpcMethod::Logs(filter))
+    }
+
+    /// The gas of a generated contract function sent from the context's address.
+    pub fn estimate_gas<O>(
+        &mut self,
+        call: &PreparedCall<O>,
+        options: &Options,
+    ) -> BatchHandle<U256> {
+        let request = self
+            .context
+            .call_request(call.target, &call.calldata, options);
+        self.add(EVMRpcMethod::EstimateGas(Box::new(request), None))
+    }
+
+    pub fn code(&mut self, address: Address, block: BlockNumber) -> BatchHandle<Bytes> {
+        self.add(EVMRpcMethod::Code(address, block))
+    }
+
+    pub fn storage_at(
+        &mut self,
+        address: Address,
+        slot: H256,
+        block: BlockNumber,
+    ) -> BatchHandle<H256> {
+        self.add(EVMRpcMethod::StorageAt(address, slot, block))
+    }
+
+    /// The EIP-1186 proof of the account and the storage `keys`.
+    pub fn proof(
+        &mut self,
+        address: Address,
+        keys: Vec<H256>,
+        block: BlockNumber,
+    ) -> BatchHandle<Option<Proof>> {
+        self.add(EVMRpcMethod::Proof(address, keys, block))
+    }
+
+    pub fn len(&self) -> usize {
+        self.methods.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.methods.is_empty()
+    }
+
+    /// Sends every request in one JSON-RPC batch.
+    pub async fn execute(&self) -> Result<BatchResults, ic_web3_rs::Error> {
+        let results = self.context.batch_call(&self.methods).await?;
+        if results.len() != self.methods.len() {
+            return Err(ic_web3_rs::Error::InvalidResponse(format!(
+                "{} results were returned for {} requests",
+                results.len(),
+                self.methods.len()
+            )));
+        }
+        Ok(BatchResults {
+            results: results.into_iter().map(Some).collect(),
+        })
+    }
+
+    fn add_with<T>(&mut self, method: EVMRpcMethod, decode: Decode<T>) -> BatchHandle<T> {
+        self.methods.push(method);
+        BatchHandle {
+            index: self.methods.len() - 1,
+            decode,
+        }
+    }
+}
+
+pub struct BatchResults {
+    results: Vec<Option<Result<Value, ic_web3_rs::Error>>>,
+}
+
+impl BatchResults {
+    /// Decodes the result of a request. Each result can be taken once. Errors
+    /// of reverted calls contain the decoded revert reason.
+    pub fn take<T>(&mut self, handle: &BatchHandle<T>) -> Result<T, ic_web3_rs::Error> {
+        let result = self
+            .results
+            .get_mut(handle.index)
+            .and_then(Option::take)
+            .ok_or_else(|| {
+                ic_web3_rs::Error::InvalidResponse(format!(
+                    "there is no result {} or it was taken already",
+                    handle.index
+                ))
+            })?;
+        (handle.decode)(result.map_err(with_revert_reason)?)
+    }
+}
@@ -0,0 +1,318 @@
+//! A builder for [`Web3Context`] covering every setting of the context.
+//!
+//! ```ignore
+//! let context = Web3ContextBuilder::default()
+//!     .url("https://eth-mainnet.g.alchemy.com/v2/<api key>")
+//!     .chain(&chains::ETHEREUM)
+//!     .signer(Signer::new("key_1"))
+//!     .from(address)
+//!     .fee_limits(limits)
+//!     .build()?;
+//! ```
+//!
+//! Providers which take the API key in a header instead of the URL are
+//! configured with [`Web3ContextBuilder::header`] or
+//! [`Web3ContextBuilder::bearer_auth`]. Setting headers, the cycles or the
+//! transform function makes the context use its own outcall transport, which
+//! doesn't support per-call `CallOptions`.
+
+use crate::{
+    chains::Chain, context::Web3Context, fee_limits::FeeLimits, signer::Signer,
+    transport::OutcallTransport, web3_provider::RPC_CALL_MAX_RETRY,
+};
+use derive_builder::{Builder, UninitializedFieldError};
+use ic_web3_rs::{
+    transports::{ic_http_client::CallOptions, Either},
+    types::Address,
+};
+use std::fmt;
+
+#[derive(Builder, Clone, Debug)]
+#[builder(
+    name = "Web3ContextBuilder",
+    public,
+    build_fn(
+        private,
+        name = "build_settings",
+        validate = "Self::validate",
+        error = "Web3ContextBuilderError"
+    )
+)]
+struct Web3ContextSettings {
+    /// The JSON-RPC endpoint. IC HTTP outcalls require HTTPS.
+    #[builder(setter(into))]
+    url: String,
+    /// The largest response the transport accepts, 500 KB by default.
+    #[builder(setter(strip_option), default)]
+    max_resp: Option<u64>,
+    /// See [`Web3Context::with_call_options`]. Can't be combined with the
+    /// outcall settings below.
+    #[builder(default)]
+    call_options: CallOptions,
+    /// Sent with every request, see [`Web3ContextBuilder::header`].
+    #[builder(setter(custom), default)]
+    headers: Vec<(String, String)>,
+    /// The cycles attached to every request. Estimated from the size of the
+    /// request when not set.
+    #[builder(setter(strip_option), default)]
+    cycles: Option<u128>,
+    /// The canister method which transforms the responses, `transform` by
+    /// default.
+    #[builder(setter(into, strip_option), default)]
+    transform: Option<String>,
+    chain_id: u64,
+    /// See [`Web3Context::with_eip1559`]. Detected when not set.
+    #[builder(setter(strip_option), default)]
+    eip1559: Option<bool>,
+    signer: Signer,
+    /// The address of the signer. Required by [`Web3ContextBuilder::build`].
+    #[builder(setter(strip_option), default)]
+    from: Option<Address>,
+    #[builder(default = "RPC_CALL_MAX_RETRY")]
+    max_retry: u8,
+    #[builder(default)]
+    access_list: bool,
+    #[builder(default)]
+    simulation: bool,
+    #[builder(default)]
+    dry_run: bool,
+    #[builder(default)]
+    fee_limits: FeeLimits,
+}
+
+/// The error of [`Web3ContextBuilder::build`].
+#[derive(Debug)]
+pub enum Web3ContextBuilderError {
+    /// A required setting is missing.
+    UninitializedField(&'static str),
+    /// A setting is invalid.
+    ValidationError(String),
+    /// The settings are valid, but the context couldn't be created, e.g.
+    /// because the signer's public key couldn't be fetched.
+    ContextError(ic_web3_rs::Error),
+}
+
+impl fmt::Display for Web3ContextBuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UninitializedField(field) => write!(f, "`{}` must be initialized", field),
+            Self::ValidationError(error) => write!(f, "{}", error),
+            Self::ContextError(error) => write!(f, "failed to create the context: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Web3ContextBuilderError {}
+
+impl From<UninitializedFieldError> for Web3ContextBuilderError {
+    fn from(error: UninitializedFieldError) -> Self {
+        Self::UninitializedField(error.field_name())
+    }
+}
+
+impl From<String> for Web3ContextBuilderError {
+    fn from(error: String) -> Self {
+        Self::ValidationError(error)
+    }
+}
+
+impl Web3ContextBuilder {
+    /// Adds a header to every request, e.g. `x-api-key`.
+    pub fn header(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.headers
+            .get_or_insert_with(Vec::new)
+            .push((name.into(), value.into()));
+        self
+    }
+
+    /// Authenticates every request with `Authorization: Bearer <token>`.
+    pub fn bearer_auth(&mut self, token: impl AsRef<str>) -> &mut Self {
+        self.header("Authorization", format!("Bearer {}", token.as_ref()))
+    }
+
+    /// Sets the chain id and EIP-1559 support of a preset.
+    pub fn chain(&mut self, chain: &Chain) -> &mut Self {
+        self.chain_id = Some(chain.chain_id);
+        self.eip1559 = Some(Some(chain.eip1559));
+        self
+    }
+
+    /// Signs with the canister's own account for the key `key_name`.
+    pub fn key_name(&mut self, key_name: impl Into<String>) -> &mut Self {
+        self.signer = Some(Signer::new(key_name));
+        self
+    }
+
+    pub fn build(&self) -> Result<Web3Context, Web3ContextBuilderError> {
+        let settings = self.build_settings()?;
+        let from = settings
+            .from
+            .ok_or(Web3ContextBuilderError::UninitializedField("from"))?;
+        settings.into_context(from)
+    }
+
+    /// Like [`Web3ContextBuilder::build`], but `from` is derived from the
+    /// signer's public key when it isn't set.
+    pub async fn build_with_derived_address(&self) -> Result<Web3Context, Web3ContextBuilderError> {
+        let settings = self.build_settings()?;
+        let from = match settings.from {
+            Some(from) => from,
+            None => settings
+                .signer
+                .address()
+                .await
+                .map_err(Web3ContextBuilderError::ContextError)?,
+        };
+        settings.into_context(from)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(url) = &self.url {
+            if !url.starts_with("https://") {
+                return Err(format!("{} is not an HTTPS URL", url));
+            }
+        }
+        if self.chain_id == Some(0) {
+            return Err("the chain id must not be 0".to_string());
+        }
+        if let Some(signer) = &self.signer {
+            if signer.key_name.is_empty() {
+                return Err("the key name must not be empty".to_string());
+            }
+        }
+        if let Some(Some(max_resp)) = self.max_resp {
+            if max_resp == 0 {
+                return Err("the maximum response size must not be 0".to_string());
+            }
+        }
+        for (name, value) in self.headers.iter().flatten() {
+            if name.is_empty() || !name.bytes().all(|b| b.is_ascii_graphic() && b != b':') {
+                return Err(format!("{:?} is not a valid header name", name));
+            }
+            if value.bytes().any(|b| b == b'\r' || b == b'\n') {
+                return Err(format!(
+                    "the value of the header {} contains a line break",
+                    name
+                ));
+            }
+        }
+        if self.cycles == Some(Some(0)) {
+            return Err("the cycles per request must not be 0".to_string());
+        }
+        if matches!(&self.transform, Some(Some(name)) if name.is_empty()) {
+            return Err("the transform function name must not be empty".to_string());
+        }
+        let outcall_settings = matches!(&self.headers, Some(headers) if !headers.is_empty())
+            || matches!(self.cycles, Some(Some(_)))
+            || matches!(self.transform, Some(Some(_)));
+        if outcall_settings
+            && matches!(&self.call_options, Some(options) if *options != CallOptions::default())
+        {
+            return Err(
+                "call options can't be combined with headers, cycles or a transform function"
+                    .to_string(),
+            );
+        }
+        if let Some(limits) = &self.fee_limits {
+            if matches!(limits.spend_budget, Some(budget) if budget.window.is_zero()) {
+                return Err("the spend budget window must not be 0".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Web3ContextSettings {
+    fn into_context(self, from: Address) -> Result<Web3Context, Web3ContextBuilderError> {
+        let context = if self.headers.is_empty()
+            && self.cycles.is_none()
+            && self.transform.is_none()
+        {
+            Web3Context::new_with_signer(&self.url, from, self.chain_id, self.signer, self.max_resp)
+                .map_err(Web3ContextBuilderError::ContextError)?
+        } else {
+            let transport = OutcallTransport::new(
+                &self.url,
+                self.headers,
+                self.max_resp,
+                self.cycles,
+                self.transform,
+            );
+            Web3Context::with_transport(Either::Right(transport), from, self.chain_id, self.signer)
+        };
+        let mut context = context
+            .with_call_options(self.call_options)
+            .with_max_retry(self.max_retry)
+            .with_access_list(self.access_list)
+            .with_simulation(self.simulation)
+            .with_dry_run(self.dry_run)
+            .with_fee_limits(self.fee_limits);
+        if let Some(eip1559) = self.eip1559 {
+            context = context.with_eip1559(eip1559);
+        }
+        Ok(context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builder() -> Web3ContextBuilder {
+        let mut builder = Web3ContextBuilder::default();
+        builder
+            .url("https://rpc.example")
+            .chain_id(1)
+            // `Signer::new` needs the canister id.
+            .signer(Signer {
+                derivation_path: vec![],
+                key_name: "key_1".to_string(),
+                ecdsa_sign_cycles: None,
+            })
+            .from(Address::zero());
+        builder
+    }
+
+    #[test]
+    fn builds_with_outcall_settings() {
+        let context = builder()
+            .bearer_auth("token")
+            .header("x-api-key", "secret")
+            .cycles(1_000_000_000)
+            .transform("transform_rpc")
+            .build();
+        assert!(context.is_ok());
+    }
+
+    #[test]
+    fn outcall_settings_are_validated() {
+        let error = |builder: &Web3ContextBuilder| match builder.build() {
+            Err(Web3ContextBuilderError::ValidationError(error)) => error,
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        };
+        assert!(error(builder().header("", "secret")).contains("header name"));
+        assert!(error(builder().header("x-api-key", "a\r\nb")).contains("line break"));
+        assert!(error(builder().cycles(0)).contains("cycles"));
+        assert!(error(builder().transform("")).contains("transform"));
+        let options = ic_web3_rs::transports::ic_http_client::CallOptionsBuilder::default()
+            .max_resp(Some(1_000))
+            .cycles(None)
+            .transform(None)
+            .build()
+            .unwrap();
+        assert!(error(
+            builder()
+                .header("x-api-key", "secret")
+                .call_options(options)
+        )
+        .contains("call options"));
+    }
+
+    #[test]
+    fn missing_settings_are_reported() {
+        assert!(matches!(
+            Web3ContextBuilder::default().build(),
+            Err(Web3ContextBuilderError::UninitializedField("url"))
+        ));
+    }
+}
@@ -0,0 +1,141 @@
+//! Presets for common EVM networks.
+
+use crate::{
+    context::Web3Context,
+    multicall::{Multicall, MULTICALL3_ADDRESS},
+};
+use ic_web3_rs::types::Address;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chain {
+    pub name: &'static str,
+    pub chain_id: u64,
+    /// Whether to send EIP-1559 transactions rather than legacy ones.
+    pub eip1559: bool,
+    /// The typical time between blocks.
+    pub block_time: Duration,
+    /// The blocks to wait for before treating a transaction or log as final.
+    pub confirmations: u64,
+    /// The most blocks to query logs for at once. Common providers reject
+    /// larger ranges, and their responses may not fit in an HTTP outcall.
+    pub max_log_range: u64,
+    pub multicall3: Address,
+}
+
+pub const ETHEREUM: Chain = Chain {
+    name: "Ethereum",
+    chain_id: 1,
+    eip1559: true,
+    block_time: Duration::from_secs(12),
+    confirmations: 12,
+    max_log_range: 2_000,
+    multicall3: MULTICALL3_ADDRESS,
+};
+
+pub const SEPOLIA: Chain = Chain {
+    name: "Sepolia",
+    chain_id: 11_155_111,
+    eip1559: true,
+    block_time: Duration::from_secs(12),
+    confirmations: 3,
+    max_log_range: 2_000,
+    multicall3: MULTICALL3_ADDRESS,
+};
+
+pub const ARBITRUM: Chain = Chain {
+    name: "Arbitrum One",
+    chain_id: 42_161,
+    eip1559: true,
+    block_time: Duration::from_millis(250),
+    confirmations: 20,
+    max_log_range: 10_000,
+    multicall3: MULTICALL3_ADDRESS,
+};
+
+pub const OPTIMISM: Chain = Chain {
+    name: "OP Mainnet",
+    chain_id: 10,
+    eip1559: true,
+    block_time: Duration::from_secs(2),
+    confirmations: 10,
+    max_log_range: 10_000,
+    multicall3: MULTICALL3_ADDRESS,
+};
+
+pub const BASE: Chain = Chain {
+    name: "Base",
+    chain_id: 8_453,
+    eip1559: true,
+    block_time: Duration::from_secs(2),
+    confirmations: 10,
+    max_log_range: 10_000,
+    multicall3: MULTICALL3_ADDRESS,
+};
+
+pub const POLYGON: Chain = Chain {
+    name: "Polygon PoS",
+    chain_id: 137,
+    eip1559: true,
+    block_time: Duration::from_secs(2),
+    confirmations: 128,
+    max_log_range: 2_000,
+    multicall3: MULTICALL3_ADDRESS,
+};
+
+/// BNB Smart Chain. Blocks have a base fee of zero, so the gas price is what
+/// matters and legacy transactions are used.
+pub const BSC: Chain = Chain {
+    name: "BNB Smart Chain",
+    chain_id: 56,
+    eip1559: false,
+    block_time: Duration::from_millis(750),
+    confirmations: 15,
+    max_log_range: 5_000,
+    multicall3: MULTICALL3_ADDRESS,
+};
+
+/// The Avalanche C-Chain. Blocks are final once accepted.
+pub const AVALANCHE: Chain = Chain {
+    name: "Avalanche C-Chain",
+    chain_id: 43_114,
+    eip1559: true,
+    block_time: Duration::from_secs(2),
+    confirmations: 1,
+    max_log_range: 2_048,
+    multicall3: MULTICALL3_ADDRESS,
+};
+
+pub const CHAINS: &[Chain] = &[
+    ETHEREUM, SEPOLIA, ARBITRUM, OPTIMISM, BASE, POLYGON, BSC, AVALANCHE,
+];
+
+pub fn by_chain_id(chain_id: u64) -> Option<&'static Chain> {
+    CHAINS.iter().find(|chain| chain.chain_id == chain_id)
+}
+
+impl Chain {
+    /// A batch for the chain's Multicall3 deployment.
+    pub fn multicall(&self) -> Multicall {
+        Multicall::new().with_address(self.multicall3)
+    }
+}
+
+impl Web3Context {
+    /// Like [`Web3Context::new`], with the chain id and EIP-1559 support of
+    /// `chain`.
+    pub fn for_chain(
+        url: &str,
+        chain: &Chain,
+        from: Address,
+        key_name: String,
+        max_resp: Option<u64>,
+    ) -> Result<Self, ic_web3_rs::error::Error> {
+        Ok(Self::new(url, from, chain.chain_id, key_name, max_resp)?.with_eip1559(chain.eip1559))
+    }
+
+    /// The preset of the context's chain, if there is one.
+    pub fn chain(&self) -> Option<&'static Chain> {
+        by_chain_id(self.chain_id())
+    }
+}
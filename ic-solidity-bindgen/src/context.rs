@@ -1,10 +1,33 @@
+use crate::fee_limits::FeeLimits;
+use crate::outbox;
+use crate::revert::with_revert_reason;
+use crate::rpc_methods::{AccessListWithGasUsed, EVMRpcMethod};
+use crate::send_error::is_already_known;
+use crate::signer::Signer;
+use crate::transport::ContextTransport;
+use crate::types::{Signature, SignedTransaction};
+use crate::web3_provider::RPC_CALL_MAX_RETRY;
 use crate::Web3Provider;
-use ic_web3_rs::api::Eth;
-use ic_web3_rs::transports::ICHttp;
-use ic_web3_rs::types::Address;
-use ic_web3_rs::Web3;
+use ic_web3_rs::api::{Accounts, Eth, Namespace};
+use ic_web3_rs::contract::Options;
+use ic_web3_rs::error::TransportError;
+use ic_web3_rs::ic::{ic_raw_sign, recover_address};
+use ic_web3_rs::transports::ic_http_client::CallOptions;
+use ic_web3_rs::transports::{Either, ICHttp};
+use ic_web3_rs::types::{
+    Address, BlockId, BlockNumber, Bytes, CallRequest, TransactionParameters, H256, U64,
+};
+use ic_web3_rs::{BatchTransport, Transport, Web3};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::sync::Arc;
 
+thread_local! {
+    // Whether a chain supports EIP-1559 doesn't change, so it is only looked
+    // up once per chain.
+    static EIP1559_SUPPORT: RefCell<HashMap<u64, bool>> = RefCell::new(HashMap::new());
+}
+
 /// Common data associated with multiple contracts.
 #[derive(Clone)]
 pub struct Web3Context(Arc<Web3ContextInner>);
@@ -14,14 +37,22 @@ pub trait Context {
     fn provider(&self, contract: Address, abi: &[u8]) -> Self::Provider;
 }
 
+#[derive(Clone)]
 struct Web3ContextInner {
     from: Address,
     // We are not expecting to interact with the chain frequently,
     // and the websocket transport has problems with ping.
     // So, the Http transport seems like the best choice.
-    eth: Eth<ICHttp>,
+    eth: Eth<ContextTransport>,
     chain_id: u64,
-    key_name: String,
+    signer: Signer,
+    access_list: bool,
+    simulate: bool,
+    dry_run: bool,
+    fee_limits: FeeLimits,
+    eip1559: Option<bool>,
+    max_retry: u8,
+    call_options: CallOptions,
 }
 
 impl Web3Context {
@@ -31,24 +62,184 @@ impl Web3Context {
         chain_id: u64,
         key_name: String,
         max_resp: Option<u64>,
+    ) -> Result<Self, ic_web3_rs::error::Error> {
+        Self::new_with_signer(url, from, chain_id, Signer::new(key_name), max_resp)
+    }
+
+    /// Like [`Web3Context::new`], but signs with an arbitrary tECDSA key.
+    /// `from` must be the address of that key.
+    pub fn new_with_signer(
+        url: &str,
+        from: Address,
+        chain_id: u64,
+        signer: Signer,
+        max_resp: Option<u64>,
     ) -> Result<Self, ic_web3_rs::error::Error> {
         let transport = ICHttp::new(url, max_resp)?;
-        let web3 = Web3::new(transport);
-        let eth = web3.eth();
+        Ok(Self::with_transport(
+            Either::Left(transport),
+            from,
+            chain_id,
+            signer,
+        ))
+    }
+
+    pub(crate) fn with_transport(
+        transport: ContextTransport,
+        from: Address,
+        chain_id: u64,
+        signer: Signer,
+    ) -> Self {
+        let eth = Web3::new(transport).eth();
         let inner = Web3ContextInner {
             eth,
             from,
             chain_id,
-            key_name,
+            signer,
+            access_list: false,
+            simulate: false,
+            dry_run: false,
+            fee_limits: FeeLimits::default(),
+            eip1559: None,
+            max_retry: RPC_CALL_MAX_RETRY,
+            call_options: CallOptions::default(),
         };
-        Ok(Self(Arc::new(inner)))
+        Self(Arc::new(inner))
+    }
+
+    /// Like [`Web3Context::new_with_signer`], but `from` is derived from the
+    /// signer's public key, so it can't diverge from the signing key.
+    pub async fn new_with_derived_address(
+        url: &str,
+        chain_id: u64,
+        signer: Signer,
+        max_resp: Option<u64>,
+    ) -> Result<Self, ic_web3_rs::error::Error> {
+        let from = signer.address().await?;
+        Self::new_with_signer(url, from, chain_id, signer, max_resp)
+    }
+
+    /// Returns a context for the same chain and transport which signs with
+    /// another key. `from` must be the address of that key.
+    pub fn with_signer(&self, from: Address, signer: Signer) -> Self {
+        let mut inner = (*self.0).clone();
+        inner.from = from;
+        inner.signer = signer;
+        Self(Arc::new(inner))
+    }
+
+    /// Like [`Web3Context::with_signer`], but `from` is derived from the
+    /// signer's public key.
+    pub async fn with_derived_signer(&self, signer: Signer) -> Result<Self, ic_web3_rs::Error> {
+        let from = signer.address().await?;
+        Ok(self.with_signer(from, signer))
+    }
+
+    /// When enabled, transactions without an explicit gas limit or access
+    /// list are signed with the access list from `eth_createAccessList`, as
+    /// long as it lowers the estimated gas.
+    pub fn with_access_list(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.0).access_list = enabled;
+        self
+    }
+
+    /// When enabled, every transaction is first executed with `eth_call`,
+    /// and is not signed if that reverts. The error then contains the
+    /// decoded revert reason, see [`crate::revert::revert_reason`].
+    pub fn with_simulation(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.0).simulate = enabled;
+        self
+    }
+
+    /// When enabled, every transaction of this context is simulated and
+    /// recorded in [`crate::dry_run::records`] instead of being signed and
+    /// broadcast. This covers contracts, replacements, sign-only contexts and
+    /// raw sends alike. The transactions returned by
+    /// [`Web3Context::sign_transaction`] are then unsigned, with an empty
+    /// `raw_transaction` and the synthetic hash of their record, and
+    /// [`Web3Context::send_raw_transaction`] doesn't broadcast anything.
+    /// `Web3Provider::send` and [`Web3Context::send_raw`] return the outcome
+    /// of [`Web3Context::dry_run`], so a revert is reported next to the
+    /// synthetic hash rather than as an error.
+    pub fn with_dry_run(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.0).dry_run = enabled;
+        self
+    }
+
+    /// Limits the fees and the total spent by transactions signed with this
+    /// context. Contracts which need other limits can be created from a clone
+    /// with different limits.
+    pub fn with_fee_limits(mut self, limits: FeeLimits) -> Self {
+        Arc::make_mut(&mut self.0).fee_limits = limits;
+        self
+    }
+
+    pub fn fee_limits(&self) -> &FeeLimits {
+        &self.0.fee_limits
+    }
+
+    /// Sets whether the chain supports EIP-1559 transactions instead of
+    /// detecting it, see [`Web3Context::supports_eip1559`].
+    pub fn with_eip1559(mut self, supported: bool) -> Self {
+        Arc::make_mut(&mut self.0).eip1559 = Some(supported);
+        self
+    }
+
+    /// Whether the chain supports EIP-1559 transactions, which is the case if
+    /// the latest block has a base fee. The answer is cached per chain id.
+    pub async fn supports_eip1559(&self) -> Result<bool, ic_web3_rs::Error> {
+        if let Some(supported) = self.0.eip1559 {
+            return Ok(supported);
+        }
+        let chain_id = self.chain_id();
+        if let Some(supported) = EIP1559_SUPPORT.with(|s| s.borrow().get(&chain_id).copied()) {
+            return Ok(supported);
+        }
+        let block = self
+            .eth()
+            .block(BlockId::Number(BlockNumber::Latest), self.call_options())
+            .await?
+            .ok_or_else(|| ic_web3_rs::Error::InvalidResponse("No block returned".to_string()))?;
+        let supported = block.base_fee_per_gas.is_some();
+        EIP1559_SUPPORT.with(|s| s.borrow_mut().insert(chain_id, supported));
+        Ok(supported)
+    }
+
+    /// How often providers created from this context retry failed requests
+    /// for fee and nonce data.
+    pub fn with_max_retry(mut self, max_retry: u8) -> Self {
+        Arc::make_mut(&mut self.0).max_retry = max_retry;
+        self
+    }
+
+    pub fn max_retry(&self) -> u8 {
+        self.0.max_retry
+    }
+
+    /// The options of the HTTP outcalls the library makes on its own, and of
+    /// transactions whose `Options` don't set any. Use it to set a transform
+    /// function or a response size limit. The `cycles` of the options are
+    /// ignored by the default transport, use [`crate::Web3ContextBuilder::cycles`]
+    /// to pay a fixed amount per request. A context built with headers,
+    /// cycles or a transform function fails calls with other options.
+    pub fn with_call_options(mut self, call_options: CallOptions) -> Self {
+        Arc::make_mut(&mut self.0).call_options = call_options;
+        self
+    }
+
+    pub fn call_options(&self) -> CallOptions {
+        self.0.call_options.clone()
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.0.dry_run
     }
 
     pub fn from(&self) -> Address {
         self.0.from
     }
 
-    pub(crate) fn eth(&self) -> &Eth<ICHttp> {
+    pub(crate) fn eth(&self) -> &Eth<ContextTransport> {
         &self.0.eth
     }
     pub fn chain_id(&self) -> u64 {
@@ -56,8 +247,296 @@ impl Web3Context {
     }
 
     pub fn key_name(&self) -> &str {
-        &self.0.key_name
+        &self.0.signer.key_name
+    }
+
+    pub fn signer(&self) -> &Signer {
+        &self.0.signer
+    }
+
+    /// Signs a transaction to `to` with the context's tECDSA key, without
+    /// broadcasting it. The nonce and gas are looked up when not set in
+    /// `options`; the fee fields are used as they are, so they must be set
+    /// for the transaction type.
+    pub async fn sign_transaction(
+        &self,
+        to: Address,
+        data: Vec<u8>,
+        mut options: Options,
+    ) -> Result<SignedTransaction, ic_web3_rs::Error> {
+        check_fee_fields(&options)?;
+        if self.is_dry_run() {
+            return self.dry_run_transaction(to, data, options).await;
+        }
+        let call_options = options
+            .call_options
+            .clone()
+            .unwrap_or_else(|| self.call_options());
+        if self.0.simulate {
+            self.simulate(to, &data, &options, call_options.clone())
+                .await?;
+        }
+        let nonce = match options.nonce {
+            Some(nonce) => nonce,
+            None => {
+                self.eth()
+                    .transaction_count(
+                        self.from(),
+                        Some(BlockNumber::Pending),
+                        call_options.clone(),
+                    )
+                    .await?
+            }
+        };
+        if self.0.access_list && options.access_list.is_none() && options.gas.is_none() {
+            self.apply_access_list(to, &data, &mut options, call_options.clone())
+                .await?;
+        }
+        let value = options.value.unwrap_or_default();
+        let gas = match options.gas {
+            Some(gas) => gas,
+            None => {
+                let request = self.call_request(to, &data, &options);
+                self.eth().estimate_gas(request, None, call_options).await?
+            }
+        };
+        // Reserved before the signing call, so that other sends made while it
+        // is awaited see the cost.
+        let reservation = self
+            .0
+            .fee_limits
+            .reserve(self.from(), nonce, &options, gas)?;
+        let tx = TransactionParameters {
+            nonce: Some(nonce),
+            to: Some(to),
+            gas,
+            gas_price: options.gas_price,
+            value,
+            data: Bytes(data.clone()),
+            chain_id: Some(self.chain_id()),
+            transaction_type: options.transaction_type,
+            access_list: options.access_list.clone(),
+            max_fee_per_gas: options.max_fee_per_gas,
+            max_priority_fee_per_gas: options.max_priority_fee_per_gas,
+        };
+        let signed = Accounts::new(self.eth().transport().clone())
+            .sign_transaction(
+                tx,
+                hex::encode(self.from()),
+                self.signer().key_info(),
+                self.chain_id(),
+            )
+            .await?;
+        let signed = SignedTransaction {
+            raw_transaction: signed.raw_transaction,
+            transaction_hash: signed.transaction_hash,
+            from: self.from(),
+            to,
+            data: Bytes(data),
+            value,
+            nonce,
+            gas,
+            gas_price: options.gas_price,
+            max_fee_per_gas: options.max_fee_per_gas,
+            max_priority_fee_per_gas: options.max_priority_fee_per_gas,
+            transaction_type: options.transaction_type,
+            access_list: options.access_list,
+        };
+        reservation.commit();
+        outbox::record(self.chain_id(), &signed);
+        Ok(signed)
+    }
+
+    /// Executes the transaction with `eth_call` from the context's address.
+    /// If it reverts, the error contains the decoded revert reason. The fee
+    /// fields of `options` are left out, as only reverts are of interest.
+    pub async fn simulate(
+        &self,
+        to: Address,
+        data: &[u8],
+        options: &Options,
+        call_options: CallOptions,
+    ) -> Result<Bytes, ic_web3_rs::Error> {
+        let request = self.simulation_request(to, data, options);
+        self.eth()
+            .call(request, None, call_options)
+            .await
+            .map_err(with_revert_reason)
+    }
+
+    /// Sets the access list generated by the node on `options`, if it lowers
+    /// the estimated gas of the transaction. The gas limit is set to the
+    /// lower estimate either way.
+    async fn apply_access_list(
+        &self,
+        to: Address,
+        data: &[u8],
+        options: &mut Options,
+        call_options: CallOptions,
+    ) -> Result<(), ic_web3_rs::Error> {
+        let mut request = self.call_request(to, data, options);
+        let method = EVMRpcMethod::CreateAccessList(
+            Box::new(self.simulation_request(to, data, options)),
+            BlockNumber::Latest,
+        );
+        // Not every node implements eth_createAccessList. As the access list
+        // is only an optimisation, the transaction is sent without one then.
+        let response = match self
+            .eth()
+            .transport()
+            .execute(method.method(), method.params(), call_options.clone())
+            .await
+        {
+            Ok(response) => response,
+            Err(_) => return Ok(()),
+        };
+        let access_list = serde_json::from_value::<AccessListWithGasUsed>(response)?.access_list;
+        if access_list.is_empty() {
+            return Ok(());
+        }
+
+        let gas_without = self
+            .eth()
+            .estimate_gas(request.clone(), None, call_options.clone())
+            .await?;
+        request.access_list = Some(access_list.clone());
+        // Legacy transactions can't carry an access list, so use EIP-2930
+        // transactions instead.
+        if request.transaction_type.unwrap_or_default().is_zero() {
+            request.transaction_type = Some(U64::from(1)); // ACCESSLISTS_TX_ID
+        }
+        let transaction_type = request.transaction_type;
+        let gas_with = self.eth().estimate_gas(request, None, call_options).await?;
+        if gas_with < gas_without {
+            options.access_list = Some(access_list);
+            options.transaction_type = transaction_type;
+            options.gas = Some(gas_with);
+        } else {
+            // Saves estimating the gas again when signing.
+            options.gas = Some(gas_without);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn call_request(&self, to: Address, data: &[u8], options: &Options) -> CallRequest {
+        CallRequest {
+            from: Some(self.from()),
+            to: Some(to),
+            gas: None,
+            gas_price: options.gas_price,
+            value: Some(options.value.unwrap_or_default()),
+            data: Some(Bytes(data.to_vec())),
+            transaction_type: options.transaction_type,
+            access_list: options.access_list.clone(),
+            max_fee_per_gas: options.max_fee_per_gas,
+            max_priority_fee_per_gas: options.max_priority_fee_per_gas,
+        }
+    }
+
+    /// Like [`Web3Context::call_request`], but without the fee fields. Given
+    /// a fee and no gas limit, nodes check the balance against their RPC gas
+    /// cap times the fee, which fails for accounts that can well afford the
+    /// transaction.
+    fn simulation_request(&self, to: Address, data: &[u8], options: &Options) -> CallRequest {
+        CallRequest {
+            gas: options.gas,
+            gas_price: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            ..self.call_request(to, data, options)
+        }
+    }
+
+    /// Sends `calls` in a single JSON-RPC batch. The results are in the order
+    /// of `calls`.
+    pub async fn batch_call(
+        &self,
+        calls: &[EVMRpcMethod],
+    ) -> Result<Vec<Result<serde_json::Value, ic_web3_rs::Error>>, ic_web3_rs::Error> {
+        let transport = self.eth().transport();
+        let calls = calls
+            .iter()
+            .map(|c| transport.prepare(c.method(), c.params()))
+            .collect::<Vec<_>>();
+
+        transport.send_batch(calls).await
     }
+
+    /// Broadcasts a transaction signed earlier. A node which already has the
+    /// transaction is not an error. The result is recorded in the outbox. Use [`crate::send_error::send_error_kind`]
+    /// to tell the other common failures apart.
+    pub async fn send_raw_transaction(
+        &self,
+        tx: &SignedTransaction,
+        call_options: CallOptions,
+    ) -> Result<H256, ic_web3_rs::Error> {
+        if self.is_dry_run() {
+            return Ok(tx.transaction_hash);
+        }
+        let res = match self
+            .eth()
+            .send_raw_transaction(tx.raw_transaction.clone(), call_options)
+            .await
+        {
+            Err(e) if is_already_known(&e, tx.transaction_hash) => Ok(tx.transaction_hash),
+            res => res,
+        };
+        outbox::record_broadcast(tx.transaction_hash, &res);
+        res
+    }
+
+    /// Signs a 32 byte hash with the context's tECDSA key. The recovery id is
+    /// chosen so that the signature recovers to [`Web3Context::from`].
+    pub async fn sign_hash(&self, hash: H256) -> Result<Signature, ic_web3_rs::Error> {
+        let signature = ic_raw_sign(hash.as_bytes().to_vec(), self.signer().key_info())
+            .await
+            .map_err(|e| ic_web3_rs::Error::Transport(TransportError::Message(e)))?;
+        let from = hex::encode(self.from());
+        let recovery_id = (0..2)
+            .find(|&id| recover_address(hash.as_bytes().to_vec(), signature.clone(), id) == from)
+            .ok_or_else(|| {
+                ic_web3_rs::Error::InvalidResponse(format!(
+                    "signature does not recover to {:?}, is `from` the address of the signer?",
+                    self.from()
+                ))
+            })?;
+        Ok(Signature {
+            r: H256::from_slice(&signature[..32]),
+            s: H256::from_slice(&signature[32..64]),
+            v: 27 + recovery_id,
+        })
+    }
+}
+
+/// Refuses options the transaction can't be signed with: EIP-1559
+/// transactions need `max_fee_per_gas`, legacy and EIP-2930 transactions
+/// need `gas_price`.
+fn check_fee_fields(options: &Options) -> Result<(), ic_web3_rs::Error> {
+    let missing = match options.transaction_type.map(|t| t.as_u64()) {
+        None | Some(0) | Some(1) if options.gas_price.is_none() => Some("gas_price"),
+        Some(2) if options.max_fee_per_gas.is_none() => Some("max_fee_per_gas"),
+        None | Some(0..=2) => None,
+        Some(other) => {
+            return Err(invalid_options(format!(
+                "transaction type {} is not supported",
+                other
+            )))
+        }
+    };
+    match missing {
+        Some(field) => Err(invalid_options(format!(
+            "{} must be set to sign the transaction",
+            field
+        ))),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn invalid_options(message: String) -> ic_web3_rs::Error {
+    ic_web3_rs::Error::Io(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        message,
+    ))
 }
 
 impl Context for Web3Context {
@@ -0,0 +1,163 @@
+//! Dry-run mode, in which transactions are simulated instead of being signed
+//! and broadcast. See [`Web3Context::with_dry_run`].
+
+use crate::{context::Web3Context, types::SignedTransaction};
+use ic_web3_rs::{
+    contract::Options,
+    signing::keccak256,
+    types::{Address, Bytes, H256, U256},
+};
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+};
+
+/// The number of records kept. Older ones are dropped when more are made
+/// before [`take_records`] is called.
+pub const MAX_RECORDS: usize = 1_000;
+
+thread_local! {
+    static RECORDS: RefCell<VecDeque<DryRunRecord>> = const { RefCell::new(VecDeque::new()) };
+    // Makes the synthetic hashes unique, even after records were dropped.
+    static NEXT_INDEX: Cell<u64> = const { Cell::new(0) };
+}
+
+/// A transaction which would have been sent.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DryRunRecord {
+    /// A synthetic hash identifying the record. No such transaction exists.
+    pub transaction_hash: H256,
+    pub chain_id: u64,
+    pub from: Address,
+    pub to: Address,
+    pub data: Bytes,
+    pub options: Options,
+    /// `None` when the simulation failed.
+    pub gas_estimate: Option<U256>,
+    /// The output of `eth_call`, or the error with the revert reason.
+    pub output: Result<Bytes, String>,
+}
+
+/// The transactions recorded since the last call to [`take_records`], at
+/// most the last [`MAX_RECORDS`].
+pub fn records() -> Vec<DryRunRecord> {
+    RECORDS.with(|r| r.borrow().iter().cloned().collect())
+}
+
+pub fn take_records() -> Vec<DryRunRecord> {
+    RECORDS.with(|r| r.take().into())
+}
+
+impl Web3Context {
+    /// Simulates a transaction with `eth_call` and `eth_estimateGas` and
+    /// records it instead of sending it. The result has the shape of
+    /// `Web3Provider::send`: the synthetic hash, and the error if the call
+    /// failed or the fee limits would have refused the transaction.
+    pub async fn dry_run(
+        &self,
+        to: Address,
+        data: Vec<u8>,
+        options: Options,
+    ) -> (H256, Option<ic_web3_rs::Error>) {
+        let (record, error) = self.record_dry_run(to, data, options).await;
+        let error = error.or_else(|| self.check_dry_run_fees(&record).err());
+        (record.transaction_hash, error)
+    }
+
+    /// What [`Web3Context::sign_transaction`] does in dry-run mode: records
+    /// the transaction, and returns it unsigned under its synthetic hash.
+    pub(crate) async fn dry_run_transaction(
+        &self,
+        to: Address,
+        data: Vec<u8>,
+        options: Options,
+    ) -> Result<SignedTransaction, ic_web3_rs::Error> {
+        let (record, error) = self.record_dry_run(to, data, options).await;
+        if let Some(error) = error {
+            return Err(error);
+        }
+        self.check_dry_run_fees(&record)?;
+        let options = record.options;
+        let gas = record.gas_estimate.unwrap_or_default();
+        Ok(SignedTransaction {
+            raw_transaction: Bytes::default(),
+            transaction_hash: record.transaction_hash,
+            from: record.from,
+            to,
+            data: record.data,
+            value: options.value.unwrap_or_default(),
+            nonce: options.nonce.unwrap_or_default(),
+            gas,
+            gas_price: options.gas_price,
+            max_fee_per_gas: options.max_fee_per_gas,
+            max_priority_fee_per_gas: options.max_priority_fee_per_gas,
+            transaction_type: options.transaction_type,
+            access_list: options.access_list,
+        })
+    }
+
+    fn check_dry_run_fees(&self, record: &DryRunRecord) -> Result<(), ic_web3_rs::Error> {
+        let gas = record.gas_estimate.unwrap_or_default();
+        self.fee_limits()
+            .check(self.from(), record.options.nonce, &record.options, gas)
+            .map(|_| ())
+    }
+
+    async fn record_dry_run(
+        &self,
+        to: Address,
+        data: Vec<u8>,
+        options: Options,
+    ) -> (DryRunRecord, Option<ic_web3_rs::Error>) {
+        let call_options = options
+            .call_options
+            .clone()
+            .unwrap_or_else(|| self.call_options());
+        let output = self
+            .simulate(to, &data, &options, call_options.clone())
+            .await;
+        let gas_estimate = match (&output, options.gas) {
+            (Err(_), _) => None,
+            (Ok(_), Some(gas)) => Some(gas),
+            (Ok(_), None) => self
+                .eth()
+                .estimate_gas(self.call_request(to, &data, &options), None, call_options)
+                .await
+                .ok(),
+        };
+
+        let index = NEXT_INDEX.with(|i| i.replace(i.get() + 1));
+        let mut preimage = self.chain_id().to_be_bytes().to_vec();
+        preimage.extend_from_slice(self.from().as_bytes());
+        preimage.extend_from_slice(to.as_bytes());
+        preimage.extend_from_slice(&data);
+        preimage.extend_from_slice(&index.to_be_bytes());
+        let transaction_hash = H256::from(keccak256(&preimage));
+
+        let (output, error) = match output {
+            Ok(output) => (Ok(output), None),
+            Err(e) => (Err(e.to_string()), Some(e)),
+        };
+        let record = DryRunRecord {
+            transaction_hash,
+            chain_id: self.chain_id(),
+            from: self.from(),
+            to,
+            data: Bytes(data),
+            options: Options {
+                call_options: None,
+                ..options
+            },
+            gas_estimate,
+            output,
+        };
+        RECORDS.with(|r| {
+            let mut records = r.borrow_mut();
+            if records.len() == MAX_RECORDS {
+                records.pop_front();
+            }
+            records.push_back(record.clone());
+        });
+        (record, error)
+    }
+}
@@ -0,0 +1,379 @@
+//! EIP-712 typed structured data hashing.
+//!
+//! Struct values are given as ABI tokens, so tuples generated from a contract
+//! ABI can be hashed directly through [`Tokenize`]. Nested structs are
+//! `Token::Tuple`s whose type is another entry of [`Eip712Types`].
+
+use crate::{context::Web3Context, types::Signature};
+use ic_web3_rs::{
+    contract::tokens::Tokenize,
+    ethabi::{self, param_type::Reader, ParamType, Token},
+    signing::keccak256,
+    types::{Address, H256, U256},
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+const DOMAIN_TYPE: &str = "EIP712Domain";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Eip712Field {
+    pub name: String,
+    /// A Solidity type such as `uint256` or `bytes32[]`, or the name of
+    /// another struct in the same [`Eip712Types`].
+    pub kind: String,
+}
+
+impl Eip712Field {
+    pub fn new(name: impl Into<String>, kind: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            kind: kind.into(),
+        }
+    }
+}
+
+/// Struct definitions by name. Fields are hashed in the given order.
+pub type Eip712Types = BTreeMap<String, Vec<Eip712Field>>;
+
+/// The `EIP712Domain` of the signature. Only the fields which are set are
+/// part of the domain type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Eip712Domain {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub chain_id: Option<U256>,
+    pub verifying_contract: Option<Address>,
+    pub salt: Option<[u8; 32]>,
+}
+
+impl Eip712Domain {
+    pub fn separator(&self) -> H256 {
+        let mut fields = Vec::new();
+        let mut values = Vec::new();
+        if let Some(name) = &self.name {
+            fields.push(Eip712Field::new("name", "string"));
+            values.push(Token::String(name.clone()));
+        }
+        if let Some(version) = &self.version {
+            fields.push(Eip712Field::new("version", "string"));
+            values.push(Token::String(version.clone()));
+        }
+        if let Some(chain_id) = self.chain_id {
+            fields.push(Eip712Field::new("chainId", "uint256"));
+            values.push(Token::Uint(chain_id));
+        }
+        if let Some(verifying_contract) = self.verifying_contract {
+            fields.push(Eip712Field::new("verifyingContract", "address"));
+            values.push(Token::Address(verifying_contract));
+        }
+        if let Some(salt) = self.salt {
+            fields.push(Eip712Field::new("salt", "bytes32"));
+            values.push(Token::FixedBytes(salt.to_vec()));
+        }
+        let types = BTreeMap::from([(DOMAIN_TYPE.to_string(), fields)]);
+        // The domain fields and values are built together above, so this
+        // can't fail.
+        hash_struct(&types, DOMAIN_TYPE, &values).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypedData {
+    pub domain: Eip712Domain,
+    pub types: Eip712Types,
+    pub primary_type: String,
+    pub message: Vec<Token>,
+}
+
+impl TypedData {
+    pub fn new(
+        domain: Eip712Domain,
+        types: Eip712Types,
+        primary_type: impl Into<String>,
+        message: impl Tokenize,
+    ) -> Self {
+        Self {
+            domain,
+            types,
+            primary_type: primary_type.into(),
+            message: message.into_tokens(),
+        }
+    }
+
+    /// `hashStruct(message)` of the primary type.
+    pub fn struct_hash(&self) -> ethabi::Result<H256> {
+        hash_struct(&self.types, &self.primary_type, &self.message)
+    }
+
+    /// The digest which is signed: `keccak256("\x19\x01" ‖ domainSeparator ‖ hashStruct(message))`.
+    pub fn digest(&self) -> ethabi::Result<H256> {
+        let mut bytes = vec![0x19, 0x01];
+        bytes.extend_from_slice(self.domain.separator().as_bytes());
+        bytes.extend_from_slice(self.struct_hash()?.as_bytes());
+        Ok(keccak256(&bytes).into())
+    }
+}
+
+/// `encodeType` of a struct: the struct itself followed by all the structs it
+/// references, sorted by name.
+pub fn encode_type(types: &Eip712Types, name: &str) -> ethabi::Result<String> {
+    let mut deps = BTreeSet::new();
+    collect_dependencies(types, name, &mut deps)?;
+    deps.remove(name);
+    std::iter::once(name)
+        .chain(deps.iter().map(String::as_str))
+        .map(|name| {
+            let fields = struct_fields(types, name)?
+                .iter()
+                .map(|f| format!("{} {}", f.kind, f.name))
+                .collect::<Vec<_>>();
+            Ok(format!("{}({})", name, fields.join(",")))
+        })
+        .collect()
+}
+
+pub fn type_hash(types: &Eip712Types, name: &str) -> ethabi::Result<H256> {
+    Ok(keccak256(encode_type(types, name)?.as_bytes()).into())
+}
+
+pub fn hash_struct(types: &Eip712Types, name: &str, values: &[Token]) -> ethabi::Result<H256> {
+    let fields = struct_fields(types, name)?;
+    if fields.len() != values.len() {
+        return Err(ethabi::Error::Other(
+            format!(
+                "{} has {} fields but {} values were given",
+                name,
+                fields.len(),
+                values.len()
+            )
+            .into(),
+        ));
+    }
+    let mut encoded = type_hash(types, name)?.as_bytes().to_vec();
+    for (field, value) in fields.iter().zip(values) {
+        encoded.extend_from_slice(&encode_value(types, &field.kind, value)?);
+    }
+    Ok(keccak256(&encoded).into())
+}
+
+fn encode_value(types: &Eip712Types, kind: &str, value: &Token) -> ethabi::Result<[u8; 32]> {
+    if let Some(inner) = array_element_type(kind) {
+        let items = match value {
+            Token::Array(items) | Token::FixedArray(items) => items,
+            _ => return Err(mismatch(kind, value)),
+        };
+        // `T[n]` must have exactly n items, `T[]` any number.
+        let length = &kind[inner.len() + 1..kind.len() - 1];
+        if !length.is_empty() && length.parse::<usize>().ok() != Some(items.len()) {
+            return Err(mismatch(kind, value));
+        }
+        let mut encoded = Vec::with_capacity(items.len() * 32);
+        for item in items {
+            encoded.extend_from_slice(&encode_value(types, inner, item)?);
+        }
+        return Ok(keccak256(&encoded));
+    }
+    if types.contains_key(kind) {
+        return match value {
+            Token::Tuple(values) => Ok(hash_struct(types, kind, values)?.0),
+            _ => Err(mismatch(kind, value)),
+        };
+    }
+    match (kind, value) {
+        ("string", Token::String(s)) => return Ok(keccak256(s.as_bytes())),
+        ("bytes", Token::Bytes(b)) => return Ok(keccak256(b)),
+        _ => {}
+    }
+    let valid = match (Reader::read(kind)?, value) {
+        (ParamType::Address, Token::Address(_)) | (ParamType::Bool, Token::Bool(_)) => true,
+        (ParamType::Int(size), Token::Int(n)) => fits_int(*n, size),
+        (ParamType::Uint(size), Token::Uint(n)) => n.bits() <= size,
+        (ParamType::FixedBytes(size), Token::FixedBytes(b)) => size <= 32 && b.len() == size,
+        _ => false,
+    };
+    if !valid {
+        return Err(mismatch(kind, value));
+    }
+    let mut word = [0u8; 32];
+    word.copy_from_slice(&ethabi::encode(std::slice::from_ref(value)));
+    Ok(word)
+}
+
+fn collect_dependencies(
+    types: &Eip712Types,
+    name: &str,
+    deps: &mut BTreeSet<String>,
+) -> ethabi::Result<()> {
+    if !deps.insert(name.to_string()) {
+        return Ok(());
+    }
+    for field in struct_fields(types, name)? {
+        let mut kind = field.kind.as_str();
+        while let Some(inner) = array_element_type(kind) {
+            kind = inner;
+        }
+        if types.contains_key(kind) {
+            collect_dependencies(types, kind, deps)?;
+        }
+    }
+    Ok(())
+}
+
+fn struct_fields<'a>(types: &'a Eip712Types, name: &str) -> ethabi::Result<&'a [Eip712Field]> {
+    types
+        .get(name)
+        .map(Vec::as_slice)
+        .ok_or_else(|| ethabi::Error::InvalidName(name.to_string()))
+}
+
+/// `Mail[]` -> `Mail`, `uint256[2][]` -> `uint256[2]`
+fn array_element_type(kind: &str) -> Option<&str> {
+    if kind.ends_with(']') {
+        kind.rfind('[').map(|i| &kind[..i])
+    } else {
+        None
+    }
+}
+
+/// Whether the two's complement `n` is in the range of `intN` with N = `size`,
+/// i.e. all bits from bit `size - 1` up are the same.
+fn fits_int(n: U256, size: usize) -> bool {
+    if size == 0 || size >= 256 {
+        return size == 256;
+    }
+    let high = n >> (size - 1);
+    high.is_zero() || high == U256::MAX >> (size - 1)
+}
+
+fn mismatch(kind: &str, value: &Token) -> ethabi::Error {
+    ethabi::Error::Other(format!("{} can't be encoded as {}", value, kind).into())
+}
+
+impl Web3Context {
+    /// Signs the EIP-712 digest of `data` with the context's tECDSA key.
+    pub async fn sign_typed_data(&self, data: &TypedData) -> Result<Signature, ic_web3_rs::Error> {
+        let digest = data
+            .digest()
+            .map_err(|e| ic_web3_rs::Error::Decoder(e.to_string()))?;
+        self.sign_hash(digest).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `Mail` example of the EIP-712 specification.
+    fn mail() -> TypedData {
+        let types = Eip712Types::from([
+            (
+                "Person".to_string(),
+                vec![
+                    Eip712Field::new("name", "string"),
+                    Eip712Field::new("wallet", "address"),
+                ],
+            ),
+            (
+                "Mail".to_string(),
+                vec![
+                    Eip712Field::new("from", "Person"),
+                    Eip712Field::new("to", "Person"),
+                    Eip712Field::new("contents", "string"),
+                ],
+            ),
+        ]);
+        let person = |name: &str, wallet: &str| {
+            Token::Tuple(vec![
+                Token::String(name.to_string()),
+                Token::Address(wallet.parse().unwrap()),
+            ])
+        };
+        let domain = Eip712Domain {
+            name: Some("Ether Mail".to_string()),
+            version: Some("1".to_string()),
+            chain_id: Some(1.into()),
+            verifying_contract: Some("CcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC".parse().unwrap()),
+            salt: None,
+        };
+        TypedData {
+            domain,
+            types,
+            primary_type: "Mail".to_string(),
+            message: vec![
+                person("Cow", "CD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"),
+                person("Bob", "bBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"),
+                Token::String("Hello, Bob!".to_string()),
+            ],
+        }
+    }
+
+    #[test]
+    fn mail_digest() {
+        let data = mail();
+        assert_eq!(
+            encode_type(&data.types, "Mail").unwrap(),
+            "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+        );
+        assert_eq!(
+            data.domain.separator(),
+            "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(
+            data.struct_hash().unwrap(),
+            "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(
+            data.digest().unwrap(),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+                .parse()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn tokens_must_match_the_field_type() {
+        let types = Eip712Types::new();
+        let address = Token::Address(Address::zero());
+        assert!(encode_value(&types, "address", &address).is_ok());
+        assert!(encode_value(&types, "string", &address).is_err());
+        assert!(encode_value(&types, "uint256", &address).is_err());
+        assert!(encode_value(&types, "uint8", &Token::Uint(256.into())).is_err());
+        assert!(encode_value(&types, "bytes32", &Token::FixedBytes(vec![0; 33])).is_err());
+        assert!(encode_value(&types, "bytes4", &Token::FixedBytes(vec![0; 32])).is_err());
+        assert!(encode_value(&types, "bytes4", &Token::FixedBytes(vec![0; 4])).is_ok());
+    }
+
+    #[test]
+    fn ints_must_be_in_range() {
+        let types = Eip712Types::new();
+        let int = |n: i64| {
+            let n = if n < 0 {
+                !U256::from(n.unsigned_abs()) + 1
+            } else {
+                U256::from(n)
+            };
+            Token::Int(n)
+        };
+        assert!(encode_value(&types, "int8", &int(127)).is_ok());
+        assert!(encode_value(&types, "int8", &int(-128)).is_ok());
+        assert!(encode_value(&types, "int8", &int(128)).is_err());
+        assert!(encode_value(&types, "int8", &int(-129)).is_err());
+        assert!(encode_value(&types, "int256", &Token::Int(U256::MAX)).is_ok());
+    }
+
+    #[test]
+    fn fixed_arrays_must_have_their_length() {
+        let types = Eip712Types::new();
+        let items = |n| Token::FixedArray(vec![Token::Bool(true); n]);
+        assert!(encode_value(&types, "bool[2]", &items(2)).is_ok());
+        assert!(encode_value(&types, "bool[2]", &items(3)).is_err());
+        assert!(encode_value(&types, "bool[]", &items(3)).is_ok());
+        assert!(
+            encode_value(&types, "bool[2][]", &Token::Array(vec![items(2), items(1)])).is_err()
+        );
+    }
+}
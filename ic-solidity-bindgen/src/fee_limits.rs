@@ -0,0 +1,257 @@
+//! Limits on what a context may spend on transactions. See
+//! [`Web3Context::with_fee_limits`](crate::Web3Context::with_fee_limits).
+
+use ic_web3_rs::{
+    contract::Options,
+    types::{Address, U256},
+};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    fmt, io,
+    time::Duration,
+};
+
+thread_local! {
+    /// The cost of the transactions signed, or being signed, by each address.
+    static SPENT: RefCell<HashMap<Address, VecDeque<Spend>>> = RefCell::new(HashMap::new());
+    static NEXT_SPEND_ID: Cell<u64> = const { Cell::new(0) };
+}
+
+struct Spend {
+    id: u64,
+    /// Nanoseconds since the epoch.
+    time: u64,
+    nonce: U256,
+    cost: U256,
+}
+
+/// Transactions which exceed any of the limits are refused before they are
+/// signed, see [`fee_limit_exceeded`]. Limits which are `None` are not
+/// enforced.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeeLimits {
+    /// The highest `maxFeePerGas`, or `gasPrice` for legacy transactions.
+    pub max_fee_per_gas: Option<U256>,
+    /// The highest `gas * fee per gas` of a single transaction.
+    pub max_fee_per_tx: Option<U256>,
+    pub spend_budget: Option<SpendBudget>,
+}
+
+/// The most an address may spend, as the sum of the fees and values of the
+/// transactions it signed, in any `window`. Fees are counted at their maximum,
+/// as the actual fee is only known once the transaction is mined. Of several
+/// transactions with the same nonce, like a speed-up and the transaction it
+/// replaces, at most one is mined, so only the most expensive is counted.
+///
+/// The spending is tracked on the heap, so the budget starts over when the
+/// canister is upgraded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpendBudget {
+    pub amount: U256,
+    pub window: Duration,
+}
+
+impl FeeLimits {
+    /// Returns the cost of the transaction, or a [`FeeLimitExceeded`] error
+    /// if it exceeds a limit.
+    pub(crate) fn check(
+        &self,
+        from: Address,
+        nonce: Option<U256>,
+        options: &Options,
+        gas: U256,
+    ) -> Result<U256, ic_web3_rs::Error> {
+        let fee_per_gas = options
+            .max_fee_per_gas
+            .or(options.gas_price)
+            .unwrap_or_default();
+        if let Some(max) = self.max_fee_per_gas {
+            if fee_per_gas > max {
+                return Err(exceeded(format!(
+                    "fee per gas {} is above the maximum of {}",
+                    fee_per_gas, max
+                )));
+            }
+        }
+        let fee = gas.saturating_mul(fee_per_gas);
+        if let Some(max) = self.max_fee_per_tx {
+            if fee > max {
+                return Err(exceeded(format!(
+                    "fee of {} is above the maximum of {} per transaction",
+                    fee, max
+                )));
+            }
+        }
+        let cost = fee.saturating_add(options.value.unwrap_or_default());
+        if let Some(budget) = self.spend_budget {
+            let spent = spends_within(from, budget.window);
+            let total = counted(spent.iter().copied().chain([(nonce, cost)]));
+            if total > budget.amount {
+                return Err(exceeded(format!(
+                    "cost of {} brings the spending in the last {:?} to {}, above the budget of {}",
+                    cost, budget.window, total, budget.amount
+                )));
+            }
+        }
+        Ok(cost)
+    }
+
+    /// Checks the transaction like [`FeeLimits::check`] and counts its cost
+    /// against the budget right away, so that transactions signed at the same
+    /// time can't exceed the budget together. The cost is released again if
+    /// the reservation is dropped without being committed.
+    pub(crate) fn reserve(
+        &self,
+        from: Address,
+        nonce: U256,
+        options: &Options,
+        gas: U256,
+    ) -> Result<SpendReservation, ic_web3_rs::Error> {
+        let cost = self.check(from, Some(nonce), options, gas)?;
+        let id = self
+            .spend_budget
+            .map(|budget| record_spend(from, nonce, cost, budget.window));
+        Ok(SpendReservation { from, id })
+    }
+}
+
+/// The cost of a transaction which is being signed. See [`FeeLimits::reserve`].
+#[must_use]
+pub(crate) struct SpendReservation {
+    from: Address,
+    /// `None` when there is no budget, or once committed.
+    id: Option<u64>,
+}
+
+impl SpendReservation {
+    /// Keeps the cost counted, as the transaction was signed.
+    pub(crate) fn commit(mut self) {
+        self.id = None;
+    }
+}
+
+impl Drop for SpendReservation {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            SPENT.with(|s| {
+                if let Some(spent) = s.borrow_mut().get_mut(&self.from) {
+                    spent.retain(|spend| spend.id != id);
+                }
+            })
+        }
+    }
+}
+
+/// The error returned for transactions which exceed a [`FeeLimits`] limit.
+/// It is wrapped in an [`ic_web3_rs::Error::Io`] of the kind
+/// [`io::ErrorKind::PermissionDenied`], see [`fee_limit_exceeded`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeLimitExceeded(pub String);
+
+impl fmt::Display for FeeLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fee limit exceeded: {}", self.0)
+    }
+}
+
+impl std::error::Error for FeeLimitExceeded {}
+
+/// Returns the limit `error` is about, if a [`FeeLimits`] limit refused the
+/// transaction.
+pub fn fee_limit_exceeded(error: &ic_web3_rs::Error) -> Option<&FeeLimitExceeded> {
+    match error {
+        ic_web3_rs::Error::Io(error) => error.get_ref()?.downcast_ref(),
+        _ => None,
+    }
+}
+
+/// The cost of the transactions signed by `from` in the last `window`, as
+/// counted against the [`SpendBudget`].
+pub fn spent_within(from: Address, window: Duration) -> U256 {
+    counted(spends_within(from, window))
+}
+
+/// The nonces and costs of the transactions signed by `from` in the last
+/// `window`.
+fn spends_within(from: Address, window: Duration) -> Vec<(Option<U256>, U256)> {
+    let since = ic_cdk::api::time().saturating_sub(window.as_nanos() as u64);
+    SPENT.with(|s| {
+        s.borrow().get(&from).map_or(vec![], |spent| {
+            spent
+                .iter()
+                .filter(|spend| spend.time >= since)
+                .map(|spend| (Some(spend.nonce), spend.cost))
+                .collect()
+        })
+    })
+}
+
+/// Sums `spends`, counting only the most expensive of the transactions with
+/// the same nonce. The nonce is `None` when it's not known, e.g. in dry-run
+/// mode.
+fn counted(spends: impl IntoIterator<Item = (Option<U256>, U256)>) -> U256 {
+    let mut by_nonce: HashMap<U256, U256> = HashMap::new();
+    let mut total = U256::zero();
+    for (nonce, cost) in spends {
+        match nonce {
+            Some(nonce) => {
+                let max = by_nonce.entry(nonce).or_default();
+                *max = (*max).max(cost);
+            }
+            None => total = total.saturating_add(cost),
+        }
+    }
+    by_nonce
+        .values()
+        .fold(total, |sum, cost| sum.saturating_add(*cost))
+}
+
+/// Records the cost of a transaction and returns the id of the record. Costs
+/// older than `keep` are forgotten.
+fn record_spend(from: Address, nonce: U256, cost: U256, keep: Duration) -> u64 {
+    let now = ic_cdk::api::time();
+    let since = now.saturating_sub(keep.as_nanos() as u64);
+    let id = NEXT_SPEND_ID.with(|next| next.replace(next.get() + 1));
+    SPENT.with(|s| {
+        let mut s = s.borrow_mut();
+        let spent = s.entry(from).or_default();
+        while spent.front().is_some_and(|spend| spend.time < since) {
+            spent.pop_front();
+        }
+        spent.push_back(Spend {
+            id,
+            time: now,
+            nonce,
+            cost,
+        });
+    });
+    id
+}
+
+fn exceeded(message: String) -> ic_web3_rs::Error {
+    ic_web3_rs::Error::Io(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        FeeLimitExceeded(message),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_largest_attempt_per_nonce_counts() {
+        let spends = [
+            (Some(U256::from(1)), U256::from(100)),
+            // A speed-up of nonce 1, and a cancel which is cheaper.
+            (Some(U256::from(1)), U256::from(110)),
+            (Some(U256::from(1)), U256::from(5)),
+            (Some(U256::from(2)), U256::from(50)),
+            (None, U256::from(7)),
+            (None, U256::from(7)),
+        ];
+        assert_eq!(counted(spends), U256::from(110 + 50 + 7 + 7));
+        assert_eq!(counted([]), U256::zero());
+    }
+}
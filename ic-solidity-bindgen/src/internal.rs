@@ -1,8 +1,11 @@
+use crate::context::invalid_options;
 use ic_web3_rs::contract::tokens::{Detokenize, Tokenizable};
-use ic_web3_rs::contract::Error;
+use ic_web3_rs::contract::{Error, Options};
 use ic_web3_rs::ethabi::Token;
+use ic_web3_rs::types::U256;
 
 /// For types which might come up in contracts which are not yet implemented in web3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Unimplemented {}
 impl Tokenizable for Unimplemented {
     fn from_token(_: Token) -> Result<Self, Error>
@@ -31,3 +34,27 @@ impl Detokenize for Empty {
         }
     }
 }
+
+/// The options of a call to a payable function. `value` replaces any value
+/// set in `options`.
+pub fn payable_options(options: Option<Options>, value: U256) -> Option<Options> {
+    Some(Options {
+        value: Some(value),
+        ..options.unwrap_or_default()
+    })
+}
+
+/// Checks that no value is sent to a function which is not payable, as the
+/// call would revert. The error is an `Io` error of the kind `InvalidInput`.
+pub fn non_payable_options(
+    func: &str,
+    options: Option<Options>,
+) -> Result<Option<Options>, ic_web3_rs::Error> {
+    match options.as_ref().and_then(|o| o.value) {
+        Some(value) if !value.is_zero() => Err(invalid_options(format!(
+            "{} is not payable, but a value of {} was given",
+            func, value
+        ))),
+        _ => Ok(options),
+    }
+}
@@ -2,13 +2,34 @@
 #[doc(hidden)]
 pub mod internal;
 
+mod account;
+pub mod batch;
+mod builder;
+pub mod chains;
 mod context;
+pub mod dry_run;
+pub mod eip712;
+pub mod fee_limits;
+pub mod multicall;
+pub mod outbox;
+pub mod personal_sign;
 mod providers;
+pub mod replacement;
+pub mod revert;
+pub mod send_error;
+mod sign_only;
+mod signer;
+mod transport;
+mod tx_params;
 pub mod types;
+pub mod verify;
 mod web3_provider;
 pub mod rpc_methods;
 
+pub use builder::{Web3ContextBuilder, Web3ContextBuilderError};
 pub use providers::{CallProvider, LogProvider, SendProvider};
+pub use sign_only::{SignOnlyContext, SignOnlyProvider};
+pub use signer::Signer;
 pub use web3_provider::Web3Provider;
 
 // Re-export the macros
@@ -0,0 +1,190 @@
+//! Batching of contract calls into a single `eth_call` to Multicall3's
+//! `aggregate3`.
+//!
+//! Generated contracts have a `prepare_<fn>` method for each view function,
+//! which returns a [`PreparedCall`] instead of calling the function:
+//!
+//! ```ignore
+//! let mut multicall = Multicall::new();
+//! let a = multicall.add(token_a.prepare_balance_of(owner)?, false);
+//! let b = multicall.add(token_b.prepare_balance_of(owner)?, true);
+//! let results = multicall.execute(&context, CallOptions::default()).await?;
+//! let a: U256 = results.get(&a)?;
+//! ```
+
+use crate::{context::Web3Context, revert::RevertReason};
+use ic_web3_rs::{
+    contract::tokens::Detokenize,
+    ethabi::{self, ParamType, Token},
+    transports::ic_http_client::CallOptions,
+    types::{Address, H160, U256},
+};
+use std::marker::PhantomData;
+
+/// The address Multicall3 is deployed at on most chains.
+pub const MULTICALL3_ADDRESS: Address = H160([
+    0xca, 0x11, 0xbd, 0xe0, 0x59, 0x77, 0xb3, 0x63, 0x11, 0x67, 0x02, 0x88, 0x62, 0xbe, 0x2a, 0x17,
+    0x39, 0x76, 0xca, 0x11,
+]);
+
+/// `aggregate3((address,bool,bytes)[])`
+const AGGREGATE3_SELECTOR: [u8; 4] = [0x82, 0xad, 0x56, 0xcb];
+
+/// A call which has been encoded but not executed, with the output types to
+/// decode its result into `O`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedCall<O> {
+    pub target: Address,
+    pub calldata: Vec<u8>,
+    pub outputs: Vec<ParamType>,
+    output: PhantomData<fn() -> O>,
+}
+
+impl<O> PreparedCall<O> {
+    pub fn new(target: Address, calldata: Vec<u8>, outputs: Vec<ParamType>) -> Self {
+        Self {
+            target,
+            calldata,
+            outputs,
+            output: PhantomData,
+        }
+    }
+}
+
+/// Identifies the result of a call added to a [`Multicall`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallHandle<O> {
+    index: usize,
+    outputs: Vec<ParamType>,
+    output: PhantomData<fn() -> O>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Multicall {
+    address: Address,
+    calls: Vec<(Address, bool, Vec<u8>)>,
+}
+
+impl Default for Multicall {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Multicall {
+    pub fn new() -> Self {
+        Self {
+            address: MULTICALL3_ADDRESS,
+            calls: vec![],
+        }
+    }
+
+    /// Uses Multicall3 at `address`, for chains where it is not deployed at
+    /// [`MULTICALL3_ADDRESS`].
+    pub fn with_address(mut self, address: Address) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Adds a call to the batch. If `allow_failure` is false and the call
+    /// reverts, the whole batch fails.
+    pub fn add<O>(&mut self, call: PreparedCall<O>, allow_failure: bool) -> CallHandle<O> {
+        self.calls.push((call.target, allow_failure, call.calldata));
+        CallHandle {
+            index: self.calls.len() - 1,
+            outputs: call.outputs,
+            output: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// The calldata of the `aggregate3` call.
+    pub fn encode(&self) -> Vec<u8> {
+        let calls = self
+            .calls
+            .iter()
+            .map(|(target, allow_failure, calldata)| {
+                Token::Tuple(vec![
+                    Token::Address(*target),
+                    Token::Bool(*allow_failure),
+                    Token::Bytes(calldata.clone()),
+                ])
+            })
+            .collect();
+        let mut data = AGGREGATE3_SELECTOR.to_vec();
+        data.extend(ethabi::encode(&[Token::Array(calls)]));
+        data
+    }
+
+    /// Executes every call in a single `eth_call` from the context's address.
+    pub async fn execute(
+        &self,
+        context: &Web3Context,
+        call_options: CallOptions,
+    ) -> Result<MulticallResults, ic_web3_rs::Error> {
+        let output = context
+            .call_raw(self.address, self.encode(), U256::zero(), call_options)
+            .await?;
+        let result_type = ParamType::Array(Box::new(ParamType::Tuple(vec![
+            ParamType::Bool,
+            ParamType::Bytes,
+        ])));
+        let decoded = ethabi::decode(&[result_type], &output.0)
+            .map_err(|e| ic_web3_rs::Error::Decoder(e.to_string()))?;
+        let results = match decoded.into_iter().next() {
+            Some(Token::Array(results)) => results,
+            _ => vec![],
+        };
+        let results = results
+            .into_iter()
+            .filter_map(|result| match result {
+                Token::Tuple(result) => match &result[..] {
+                    [Token::Bool(success), Token::Bytes(data)] => Some((*success, data.clone())),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if results.len() != self.calls.len() {
+            return Err(ic_web3_rs::Error::InvalidResponse(format!(
+                "{} results were returned for {} calls",
+                results.len(),
+                self.calls.len()
+            )));
+        }
+        Ok(MulticallResults { results })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MulticallResults {
+    results: Vec<(bool, Vec<u8>)>,
+}
+
+impl MulticallResults {
+    /// Decodes the result of a call. A call which was allowed to fail and
+    /// reverted is returned as an error with the decoded revert reason, which
+    /// [`crate::revert::revert_reason`] recognizes.
+    pub fn get<O: Detokenize>(&self, handle: &CallHandle<O>) -> Result<O, ic_web3_rs::Error> {
+        let (success, data) = self.results.get(handle.index).ok_or_else(|| {
+            ic_web3_rs::Error::InvalidResponse(format!("there is no call {}", handle.index))
+        })?;
+        if !success {
+            return Err(ic_web3_rs::Error::Rpc(jsonrpc_core::Error {
+                code: jsonrpc_core::ErrorCode::ServerError(3),
+                message: format!("execution reverted: {}", RevertReason::decode(data)),
+                data: Some(format!("0x{}", hex::encode(data)).into()),
+            }));
+        }
+        let tokens = ethabi::decode(&handle.outputs, data)
+            .map_err(|e| ic_web3_rs::Error::Decoder(e.to_string()))?;
+        O::from_tokens(tokens).map_err(|e| ic_web3_rs::Error::Decoder(e.to_string()))
+    }
+}
@@ -0,0 +1,315 @@
+//! A record of every signed transaction, kept in stable memory so that
+//! in-flight transactions survive upgrades and traps.
+//!
+//! The outbox is disabled until [`init`] is called with a memory from the
+//! canister's `MemoryManager`. From then on, every transaction signed through
+//! a [`Web3Context`] is recorded as pending, and [`reconcile`] moves entries to
+//! mined, reverted or failed by looking up their receipts. Transactions the
+//! node rejects for their nonce, fee or the balance are marked as failed when
+//! they are broadcast. Finished entries
+//! stay in the outbox until they are removed with [`prune`].
+
+use crate::{
+    context::Web3Context,
+    send_error::{send_error_kind, SendErrorKind},
+    types::SignedTransaction,
+};
+use ic_cdk_timers::TimerId;
+use ic_stable_structures::{
+    memory_manager::VirtualMemory, storable::Bound, DefaultMemoryImpl, StableBTreeMap, Storable,
+};
+use ic_web3_rs::types::{Address, BlockNumber, H256, U256, U64};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+pub type OutboxMemory = VirtualMemory<DefaultMemoryImpl>;
+
+thread_local! {
+    static OUTBOX: RefCell<Option<StableBTreeMap<u64, OutboxEntry, OutboxMemory>>> = const { RefCell::new(None) };
+    /// The hashes of the pending entries by id, so that reconciling doesn't
+    /// have to read the whole outbox. Rebuilt by [`init`].
+    static PENDING: RefCell<BTreeMap<u64, H256>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum OutboxStatus {
+    /// Signed, and possibly broadcast, but not mined yet.
+    Pending,
+    Mined {
+        block_number: u64,
+    },
+    /// Mined, but the execution failed.
+    Reverted {
+        block_number: u64,
+    },
+    /// The nonce was taken by another transaction, for example a replacement,
+    /// or the transaction was marked as failed with [`mark_failed`].
+    Failed {
+        reason: String,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub chain_id: u64,
+    pub tx: SignedTransaction,
+    pub status: OutboxStatus,
+    /// Nanoseconds since the epoch.
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// The error of the last broadcast, if it failed without the node
+    /// rejecting the transaction for good, e.g. because the outcall failed.
+    #[serde(default)]
+    pub broadcast_error: Option<String>,
+}
+
+impl Storable for OutboxEntry {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        // Serializing plain data to JSON can't fail.
+        Cow::Owned(serde_json::to_vec(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// Enables the outbox. Entries recorded before an upgrade are loaded from
+/// `memory`, so call this from both `init` and `post_upgrade`.
+pub fn init(memory: OutboxMemory) {
+    let outbox: StableBTreeMap<u64, OutboxEntry, OutboxMemory> = StableBTreeMap::init(memory);
+    let pending = outbox
+        .iter()
+        .filter(|(_, entry)| entry.status == OutboxStatus::Pending)
+        .map(|(id, entry)| (id, entry.tx.transaction_hash))
+        .collect();
+    OUTBOX.with(|o| *o.borrow_mut() = Some(outbox));
+    PENDING.with(|p| *p.borrow_mut() = pending);
+}
+
+/// Records a signed transaction as pending. Does nothing, and returns `None`,
+/// when the outbox is not enabled.
+pub(crate) fn record(chain_id: u64, tx: &SignedTransaction) -> Option<u64> {
+    OUTBOX.with(|o| {
+        let mut outbox = o.borrow_mut();
+        let outbox = outbox.as_mut()?;
+        let id = outbox.last_key_value().map_or(0, |(id, _)| id + 1);
+        let now = ic_cdk::api::time();
+        let entry = OutboxEntry {
+            chain_id,
+            tx: tx.clone(),
+            status: OutboxStatus::Pending,
+            created_at: now,
+            updated_at: now,
+            broadcast_error: None,
+        };
+        outbox.insert(id, entry);
+        PENDING.with(|p| p.borrow_mut().insert(id, tx.transaction_hash));
+        Some(id)
+    })
+}
+
+pub fn get(id: u64) -> Option<OutboxEntry> {
+    OUTBOX.with(|o| o.borrow().as_ref()?.get(&id))
+}
+
+pub fn pending() -> Vec<(u64, OutboxEntry)> {
+    let ids = PENDING.with(|p| p.borrow().keys().copied().collect::<Vec<_>>());
+    ids.into_iter()
+        .filter_map(|id| Some((id, get(id)?)))
+        .collect()
+}
+
+/// The id and entry of the pending transaction with the hash `hash`, for
+/// example to [`mark_failed`] a transaction which could not be broadcast.
+pub fn find_pending(hash: H256) -> Option<(u64, OutboxEntry)> {
+    let id = PENDING.with(|p| {
+        p.borrow()
+            .iter()
+            .find(|(_, pending)| **pending == hash)
+            .map(|(id, _)| *id)
+    })?;
+    Some((id, get(id)?))
+}
+
+pub fn mined() -> Vec<(u64, OutboxEntry)> {
+    entries(|status| matches!(status, OutboxStatus::Mined { .. }))
+}
+
+/// Transactions which reverted or will never be mined.
+pub fn failed() -> Vec<(u64, OutboxEntry)> {
+    entries(|status| {
+        matches!(
+            status,
+            OutboxStatus::Reverted { .. } | OutboxStatus::Failed { .. }
+        )
+    })
+}
+
+/// Marks a transaction as failed, for example when it could not be broadcast
+/// and won't be retried.
+pub fn mark_failed(id: u64, reason: String) {
+    set_status(id, OutboxStatus::Failed { reason });
+}
+
+/// Records the result of broadcasting the pending transaction `hash`. Nonce,
+/// fee and balance rejections mean that it won't be mined, so the entry is
+/// marked as failed. Other errors are kept on the entry.
+pub(crate) fn record_broadcast(hash: H256, result: &Result<H256, ic_web3_rs::Error>) {
+    let (id, mut entry) = match find_pending(hash) {
+        Some(pending) => pending,
+        None => return,
+    };
+    let error = match result {
+        Ok(_) if entry.broadcast_error.is_none() => return,
+        Ok(_) => None,
+        Err(e) => match send_error_kind(e) {
+            Some(
+                SendErrorKind::NonceTooLow
+                | SendErrorKind::ReplacementUnderpriced
+                | SendErrorKind::InsufficientFunds,
+            ) => return mark_failed(id, e.to_string()),
+            _ => Some(e.to_string()),
+        },
+    };
+    entry.broadcast_error = error;
+    entry.updated_at = ic_cdk::api::time();
+    OUTBOX.with(|o| {
+        if let Some(outbox) = o.borrow_mut().as_mut() {
+            outbox.insert(id, entry);
+        }
+    })
+}
+
+fn entries(filter: impl Fn(&OutboxStatus) -> bool) -> Vec<(u64, OutboxEntry)> {
+    OUTBOX.with(|o| match o.borrow().as_ref() {
+        Some(outbox) => outbox
+            .iter()
+            .filter(|(_, entry)| filter(&entry.status))
+            .collect(),
+        None => vec![],
+    })
+}
+
+/// Removes the entries which are no longer pending and were last updated
+/// more than `age` ago, and returns how many were removed. The most recent
+/// entry is kept, so that ids are never reused.
+pub fn prune(age: Duration) -> usize {
+    let before = ic_cdk::api::time().saturating_sub(age.as_nanos() as u64);
+    OUTBOX.with(|o| {
+        let mut outbox = o.borrow_mut();
+        let outbox = match outbox.as_mut() {
+            Some(outbox) => outbox,
+            None => return 0,
+        };
+        let last = outbox.last_key_value().map(|(id, _)| id);
+        let ids = outbox
+            .iter()
+            .filter(|(id, entry)| {
+                Some(*id) != last
+                    && entry.status != OutboxStatus::Pending
+                    && entry.updated_at < before
+            })
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        for id in &ids {
+            outbox.remove(id);
+        }
+        ids.len()
+    })
+}
+
+fn set_status(id: u64, status: OutboxStatus) {
+    if status != OutboxStatus::Pending {
+        PENDING.with(|p| p.borrow_mut().remove(&id));
+    }
+    OUTBOX.with(|o| {
+        if let Some(outbox) = o.borrow_mut().as_mut() {
+            if let Some(mut entry) = outbox.get(&id) {
+                entry.status = status;
+                entry.updated_at = ic_cdk::api::time();
+                outbox.insert(id, entry);
+            }
+        }
+    })
+}
+
+/// Looks up the receipt of every pending transaction on the context's chain.
+/// Transactions without a receipt whose nonce has already been used are
+/// marked as failed. An entry whose lookups fail is skipped, and the first
+/// such error is returned after the others have been reconciled.
+pub async fn reconcile(ctx: &Web3Context) -> Result<(), ic_web3_rs::Error> {
+    let pending = pending()
+        .into_iter()
+        .filter(|(_, entry)| entry.chain_id == ctx.chain_id());
+    let mut confirmed_nonces: HashMap<Address, U256> = HashMap::new();
+    let mut first_error = None;
+    for (id, entry) in pending {
+        if let Err(e) = reconcile_entry(ctx, id, &entry, &mut confirmed_nonces).await {
+            first_error.get_or_insert(e);
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+async fn reconcile_entry(
+    ctx: &Web3Context,
+    id: u64,
+    entry: &OutboxEntry,
+    confirmed_nonces: &mut HashMap<Address, U256>,
+) -> Result<(), ic_web3_rs::Error> {
+    // The nonce is looked up before the receipt. Otherwise a transaction
+    // mined in between would have no receipt but a used nonce, and be
+    // marked as failed.
+    let confirmed_nonce = match confirmed_nonces.get(&entry.tx.from) {
+        Some(nonce) => *nonce,
+        None => {
+            let nonce = ctx
+                .eth()
+                .transaction_count(entry.tx.from, Some(BlockNumber::Latest), ctx.call_options())
+                .await?;
+            confirmed_nonces.insert(entry.tx.from, nonce);
+            nonce
+        }
+    };
+    let receipt = ctx
+        .eth()
+        .transaction_receipt(entry.tx.transaction_hash, ctx.call_options())
+        .await?;
+    if let Some(receipt) = receipt {
+        let block_number = receipt.block_number.unwrap_or_default().as_u64();
+        let status = if receipt.status == Some(U64::zero()) {
+            OutboxStatus::Reverted { block_number }
+        } else {
+            OutboxStatus::Mined { block_number }
+        };
+        set_status(id, status);
+    } else if entry.tx.nonce < confirmed_nonce {
+        set_status(
+            id,
+            OutboxStatus::Failed {
+                reason: "nonce was used by another transaction".to_string(),
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Reconciles the outbox against the context's chain every `interval`.
+pub fn start_reconciliation(ctx: Web3Context, interval: Duration) -> TimerId {
+    ic_cdk_timers::set_timer_interval(interval, move || {
+        let ctx = ctx.clone();
+        ic_cdk::spawn(async move {
+            if let Err(e) = reconcile(&ctx).await {
+                ic_cdk::println!("outbox reconciliation failed: {}", e);
+            }
+        })
+    })
+}
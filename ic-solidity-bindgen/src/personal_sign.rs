@@ -0,0 +1,111 @@
+//! EIP-191 `personal_sign` signing and signature verification.
+
+use crate::{context::Web3Context, revert::revert_reason, types::Signature};
+use ic_web3_rs::{
+    ethabi::{self, Token},
+    signing::{keccak256, RecoveryError},
+    types::{Address, H256, U256},
+};
+use libsecp256k1::{Message, RecoveryId};
+
+/// Returned by ERC-1271 `isValidSignature`, which contract wallets implement
+/// to validate signatures on their behalf, when the signature is valid.
+pub const ERC1271_MAGIC_VALUE: [u8; 4] = [0x16, 0x26, 0xba, 0x7e];
+
+/// Hashes a message according to EIP-191:
+/// `keccak256("\x19Ethereum Signed Message:\n" ‖ len(message) ‖ message)`.
+pub fn hash_message(message: impl AsRef<[u8]>) -> H256 {
+    ic_web3_rs::signing::hash_message(message)
+}
+
+/// Recovers the address which signed `hash`. `signature` is `r ‖ s ‖ v`,
+/// with `v` either 0/1 or 27/28.
+pub fn recover(hash: H256, signature: &[u8]) -> Result<Address, ic_web3_rs::Error> {
+    if signature.len() != 65 {
+        return Err(RecoveryError::InvalidSignature.into());
+    }
+    let recovery_id = match signature[64] {
+        v @ 0..=1 => v,
+        v @ 27..=28 => v - 27,
+        _ => return Err(RecoveryError::InvalidSignature.into()),
+    };
+    let message =
+        Message::parse_slice(hash.as_bytes()).map_err(|_| RecoveryError::InvalidMessage)?;
+    let rs = libsecp256k1::Signature::parse_standard_slice(&signature[..64])
+        .map_err(|_| RecoveryError::InvalidSignature)?;
+    let recovery_id =
+        RecoveryId::parse(recovery_id).map_err(|_| RecoveryError::InvalidSignature)?;
+    let public_key = libsecp256k1::recover(&message, &rs, &recovery_id)
+        .map_err(|_| RecoveryError::InvalidSignature)?;
+    let hash = keccak256(&public_key.serialize()[1..]);
+    Ok(Address::from_slice(&hash[12..]))
+}
+
+/// Recovers the address which `personal_sign`ed `message`.
+pub fn recover_message(
+    message: impl AsRef<[u8]>,
+    signature: &[u8],
+) -> Result<Address, ic_web3_rs::Error> {
+    recover(hash_message(message), signature)
+}
+
+/// Asks the contract wallet `wallet` whether `signature` is valid for `hash`
+/// through ERC-1271. A wallet which reverts, or doesn't answer with the magic
+/// value, doesn't accept the signature.
+///
+/// This takes a [`Web3Context`] rather than a `CallProvider`: providers
+/// decode outputs with the ABI and treat a mismatch as a bug, while a wallet's
+/// answer has to be checked by hand.
+pub async fn is_valid_erc1271_signature(
+    context: &Web3Context,
+    wallet: Address,
+    hash: H256,
+    signature: Vec<u8>,
+) -> Result<bool, ic_web3_rs::Error> {
+    // The magic value is the selector of `isValidSignature(bytes32,bytes)`.
+    let mut data = ERC1271_MAGIC_VALUE.to_vec();
+    data.extend(ethabi::encode(&[
+        Token::FixedBytes(hash.0.to_vec()),
+        Token::Bytes(signature),
+    ]));
+    // Not every contract implements ERC-1271 correctly, or at all, so the
+    // output is checked by hand instead of being decoded as a `bytes4`.
+    let output = match context
+        .call_raw(wallet, data, U256::zero(), context.call_options())
+        .await
+    {
+        Ok(output) => output.0,
+        Err(e) if revert_reason(&e).is_some() => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let mut expected = [0u8; 32];
+    expected[..4].copy_from_slice(&ERC1271_MAGIC_VALUE);
+    Ok(output.get(..32) == Some(&expected[..]))
+}
+
+impl Web3Context {
+    /// Signs `message` the way `personal_sign` does, with the context's tECDSA key.
+    pub async fn personal_sign(
+        &self,
+        message: impl AsRef<[u8]>,
+    ) -> Result<Signature, ic_web3_rs::Error> {
+        self.sign_hash(hash_message(message)).await
+    }
+
+    /// Verifies that `signer` signed `message` with `personal_sign`. Contract
+    /// wallets are asked through ERC-1271, other accounts are checked with
+    /// `ecrecover`.
+    pub async fn verify_personal_signature(
+        &self,
+        signer: Address,
+        message: impl AsRef<[u8]>,
+        signature: &[u8],
+    ) -> Result<bool, ic_web3_rs::Error> {
+        let hash = hash_message(message);
+        let code = self.eth().code(signer, None, self.call_options()).await?;
+        if code.0.is_empty() {
+            return Ok(matches!(recover(hash, signature), Ok(address) if address == signer));
+        }
+        is_valid_erc1271_signature(self, signer, hash, signature.to_vec()).await
+    }
+}
@@ -1,12 +1,11 @@
-use std::collections::HashMap;
-
 use async_trait::async_trait;
 use ic_web3_rs::contract::tokens::{Detokenize, Tokenize};
 use ic_web3_rs::contract::Options;
+use ic_web3_rs::ethabi::TopicFilter;
 use ic_web3_rs::transports::ic_http_client::CallOptions;
 use ic_web3_rs::Error;
 
-use crate::types::EventLog;
+use crate::types::EventLogs;
 
 #[async_trait]
 pub trait CallProvider {
@@ -36,5 +35,21 @@ pub trait LogProvider {
         from: u64,
         to: u64,
         call_options: CallOptions,
-    ) -> Result<HashMap<u64, Vec<EventLog>>, ic_web3_rs::Error>;
+    ) -> Result<EventLogs, ic_web3_rs::Error>;
+
+    /// Finds logs of an anonymous event. As there is no signature topic to
+    /// filter by, `topics` filters on the indexed inputs in order starting
+    /// from `topic0`, and logs are matched by their shape.
+    ///
+    /// Providers which can't filter by topics should return an error. There
+    /// is no default: under `async_trait` it would require `Self: Sync`, which
+    /// the generated bindings don't ask of their provider.
+    async fn find_anonymous(
+        &self,
+        event_name: &str,
+        from: u64,
+        to: u64,
+        topics: TopicFilter,
+        call_options: CallOptions,
+    ) -> Result<EventLogs, ic_web3_rs::Error>;
 }
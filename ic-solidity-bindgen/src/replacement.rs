@@ -0,0 +1,148 @@
+//! Speeding up and cancelling pending transactions by replacing them with a
+//! transaction of the same nonce and higher fees.
+
+use crate::{context::Web3Context, types::PendingTransaction};
+use ic_web3_rs::{
+    contract::Options,
+    transports::ic_http_client::CallOptions,
+    types::{Address, TransactionReceipt, H256, U256},
+};
+
+/// The fee increase clients require to accept a replacement for a pending
+/// transaction. Geth and most of its forks use 10%.
+pub const MIN_REPLACEMENT_FEE_BUMP_PERCENT: u64 = 10;
+
+impl Web3Context {
+    /// Re-signs the latest attempt of `pending` with the same nonce and
+    /// calldata, and fees bumped by `bump_percent`, then broadcasts it.
+    pub async fn speed_up(
+        &self,
+        pending: &mut PendingTransaction,
+        bump_percent: u64,
+        call_options: CallOptions,
+    ) -> Result<(H256, Option<ic_web3_rs::Error>), ic_web3_rs::Error> {
+        let latest = pending.latest().clone();
+        let options = bump_fees(latest.options(), bump_percent);
+        self.replace(pending, latest.to, latest.data.0, options, call_options)
+            .await
+    }
+
+    /// Replaces `pending` with a zero value transfer to the context's own
+    /// address, with fees bumped by `bump_percent`, then broadcasts it.
+    pub async fn cancel(
+        &self,
+        pending: &mut PendingTransaction,
+        bump_percent: u64,
+        call_options: CallOptions,
+    ) -> Result<(H256, Option<ic_web3_rs::Error>), ic_web3_rs::Error> {
+        let mut options = Options {
+            value: Some(U256::zero()),
+            access_list: None,
+            ..bump_fees(pending.latest().options(), bump_percent)
+        };
+        // A plain transfer costs 21 000 gas on L1, but more on some L2s such
+        // as Arbitrum, which also charge for the L1 data.
+        let request = self.call_request(self.from(), &[], &options);
+        options.gas = Some(
+            self.eth()
+                .estimate_gas(request, None, call_options.clone())
+                .await?,
+        );
+        self.replace(pending, self.from(), vec![], options, call_options)
+            .await
+    }
+
+    /// Returns the receipt of whichever attempt of `pending` was mined, if any.
+    pub async fn mined(
+        &self,
+        pending: &PendingTransaction,
+        call_options: CallOptions,
+    ) -> Result<Option<TransactionReceipt>, ic_web3_rs::Error> {
+        for hash in pending.hashes().into_iter().rev() {
+            let receipt = self
+                .eth()
+                .transaction_receipt(hash, call_options.clone())
+                .await?;
+            if receipt.is_some() {
+                return Ok(receipt);
+            }
+        }
+        Ok(None)
+    }
+
+    async fn replace(
+        &self,
+        pending: &mut PendingTransaction,
+        to: Address,
+        data: Vec<u8>,
+        options: Options,
+        call_options: CallOptions,
+    ) -> Result<(H256, Option<ic_web3_rs::Error>), ic_web3_rs::Error> {
+        let signed = self.sign_transaction(to, data, options).await?;
+        let res = self.send_raw_transaction(&signed, call_options).await;
+        let hash = signed.transaction_hash;
+        pending.push(signed);
+        Ok((hash, res.err()))
+    }
+}
+
+/// Raises every fee in `options` by `bump_percent`, but never by less than
+/// [`MIN_REPLACEMENT_FEE_BUMP_PERCENT`]. Fees are rounded up, and saturate at
+/// `U256::MAX`.
+pub fn bump_fees(options: Options, bump_percent: u64) -> Options {
+    let percent = U256::from(bump_percent.max(MIN_REPLACEMENT_FEE_BUMP_PERCENT));
+    let hundred = U256::from(100);
+    // Split as `fee = 100q + r`, so that only `q * percent` can overflow.
+    // Rounding up raises small fees by the full percentage too.
+    let bump = |fee: U256| {
+        let increase = (fee / hundred)
+            .saturating_mul(percent)
+            .saturating_add((fee % hundred * percent + 99) / hundred);
+        fee.saturating_add(increase)
+    };
+    Options {
+        gas_price: options.gas_price.map(bump),
+        max_fee_per_gas: options.max_fee_per_gas.map(bump),
+        max_priority_fee_per_gas: options.max_priority_fee_per_gas.map(bump),
+        ..options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bumped(fee: u64, bump_percent: u64) -> U256 {
+        let options = Options {
+            gas_price: Some(fee.into()),
+            ..Default::default()
+        };
+        bump_fees(options, bump_percent).gas_price.unwrap()
+    }
+
+    #[test]
+    fn bumps_by_at_least_ten_percent() {
+        assert_eq!(bumped(1_000, 0), U256::from(1_100));
+        assert_eq!(bumped(1_000, 5), U256::from(1_100));
+        assert_eq!(bumped(1_000, 25), U256::from(1_250));
+    }
+
+    #[test]
+    fn rounds_up() {
+        assert_eq!(bumped(1, 10), U256::from(2));
+        assert_eq!(bumped(15, 10), U256::from(17));
+        assert_eq!(bumped(0, 10), U256::zero());
+    }
+
+    #[test]
+    fn saturates() {
+        let options = Options {
+            max_fee_per_gas: Some(U256::MAX / 2),
+            max_priority_fee_per_gas: Some(U256::MAX),
+            ..Default::default()
+        };
+        let options = bump_fees(options, u64::MAX);
+        assert_eq!(options.max_fee_per_gas, Some(U256::MAX));
+        assert_eq!(options.max_priority_fee_per_gas, Some(U256::MAX));
+    }
+}
@@ -0,0 +1,93 @@
+//! Decoding of the revert data returned by `eth_call` and `eth_estimateGas`.
+
+use ic_web3_rs::{
+    ethabi::{self, ParamType},
+    types::U256,
+};
+use serde_json::Value;
+use std::fmt;
+
+/// `Error(string)`, used by `require` and `revert` with a message.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// `Panic(uint256)`, used by failed asserts, overflows and the like.
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevertReason {
+    Error(String),
+    Panic(U256),
+    /// A custom error. The data starts with the error's selector.
+    Custom(Vec<u8>),
+    /// Reverted without data, for example by `revert()`.
+    Empty,
+}
+
+impl RevertReason {
+    pub fn decode(data: &[u8]) -> Self {
+        if data.is_empty() {
+            return Self::Empty;
+        }
+        let decoded = if data.starts_with(&ERROR_SELECTOR) {
+            ethabi::decode(&[ParamType::String], &data[4..])
+                .ok()
+                .and_then(|mut tokens| tokens.pop()?.into_string())
+                .map(Self::Error)
+        } else if data.starts_with(&PANIC_SELECTOR) {
+            ethabi::decode(&[ParamType::Uint(256)], &data[4..])
+                .ok()
+                .and_then(|mut tokens| tokens.pop()?.into_uint())
+                .map(Self::Panic)
+        } else {
+            None
+        };
+        decoded.unwrap_or_else(|| Self::Custom(data.to_vec()))
+    }
+}
+
+impl fmt::Display for RevertReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error(reason) => write!(f, "{}", reason),
+            Self::Panic(code) => write!(f, "panic code {:#x}", code),
+            Self::Custom(data) => write!(f, "custom error 0x{}", hex::encode(data)),
+            Self::Empty => write!(f, "no reason given"),
+        }
+    }
+}
+
+/// Returns the revert reason if `error` is the node reporting a reverted call.
+pub fn revert_reason(error: &ic_web3_rs::Error) -> Option<RevertReason> {
+    let error = match error {
+        ic_web3_rs::Error::Rpc(error) => error,
+        _ => return None,
+    };
+    // Geth and most clients use code 3 when revert data is attached. Others
+    // only say so in the message.
+    if error.code.code() != 3 && !error.message.contains("revert") {
+        return None;
+    }
+    let data = match &error.data {
+        Some(Value::String(data)) => Some(data.as_str()),
+        // Some clients nest the data, e.g. `{"data": "0x..."}`.
+        Some(Value::Object(object)) => object.get("data").and_then(Value::as_str),
+        _ => None,
+    };
+    let data = data
+        .and_then(|data| hex::decode(data.trim_start_matches("0x")).ok())
+        .unwrap_or_default();
+    Some(RevertReason::decode(&data))
+}
+
+/// Rewrites the message of a revert error to contain the decoded reason.
+/// Other errors are returned unchanged.
+pub(crate) fn with_revert_reason(error: ic_web3_rs::Error) -> ic_web3_rs::Error {
+    match (revert_reason(&error), error) {
+        (Some(reason), ic_web3_rs::Error::Rpc(error)) => {
+            ic_web3_rs::Error::Rpc(jsonrpc_core::Error {
+                message: format!("execution reverted: {}", reason),
+                ..error
+            })
+        }
+        (_, error) => error,
+    }
+}
@@ -1,15 +1,76 @@
 use ic_web3_rs::{
     helpers,
-    types::{Address, BlockNumber, U256},
+    types::{
+        AccessList, Address, Block, BlockNumber, Bytes, CallRequest, FeeHistory, Filter, Log,
+        Proof, Transaction, TransactionReceipt, H256, U256, U64,
+    },
 };
+use serde::Deserialize;
 use serde_json::Value;
 
+#[derive(Debug, Clone)]
 pub enum EVMRpcMethod {
     TransactionCount(Address, BlockNumber),
     GasPrice,
     /// BlockCount, BlockTag, RewardPercentile
     FeeHistory(U256, BlockNumber, Option<Vec<f64>>),
     MaxPriorityFeePerGas,
+    /// Transaction, BlockTag
+    CreateAccessList(Box<CallRequest>, BlockNumber),
+    /// Transaction, BlockTag
+    Call(Box<CallRequest>, BlockNumber),
+    /// Address, BlockTag
+    Balance(Address, BlockNumber),
+    BlockNumber,
+    /// BlockTag, whether to include the full transactions
+    BlockByNumber(BlockNumber, bool),
+    /// TransactionHash
+    TransactionReceipt(H256),
+    Logs(Filter),
+    /// Transaction, BlockTag
+    EstimateGas(Box<CallRequest>, Option<BlockNumber>),
+    ChainId,
+    /// Address, BlockTag
+    Code(Address, BlockNumber),
+    /// Address, Slot, BlockTag. The slot is sent as 32 bytes, which every
+    /// node accepts, rather than as a quantity.
+    StorageAt(Address, H256, BlockNumber),
+    /// Address, StorageKeys, BlockTag
+    Proof(Address, Vec<H256>, BlockNumber),
+}
+
+/// The result of `eth_createAccessList`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListWithGasUsed {
+    pub access_list: AccessList,
+    pub gas_used: U256,
+}
+
+/// The decoded result of an [`EVMRpcMethod`], in the variant of the same name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EVMRpcResponse {
+    TransactionCount(U256),
+    GasPrice(U256),
+    FeeHistory(FeeHistory),
+    MaxPriorityFeePerGas(U256),
+    CreateAccessList(AccessListWithGasUsed),
+    Call(Bytes),
+    Balance(U256),
+    BlockNumber(U64),
+    /// `None` if the block doesn't exist yet.
+    BlockByNumber(Option<Block<H256>>),
+    /// [`EVMRpcMethod::BlockByNumber`] with the full transactions.
+    BlockWithTransactions(Option<Block<Transaction>>),
+    /// `None` if the transaction is not mined yet.
+    TransactionReceipt(Option<TransactionReceipt>),
+    Logs(Vec<Log>),
+    EstimateGas(U256),
+    ChainId(U256),
+    Code(Bytes),
+    StorageAt(H256),
+    /// `None` for nodes which return `null` for unknown blocks.
+    Proof(Option<Proof>),
 }
 
 impl EVMRpcMethod {
@@ -19,6 +80,18 @@ impl EVMRpcMethod {
             Self::GasPrice => "eth_gasPrice",
             Self::MaxPriorityFeePerGas => "eth_maxPriorityFeePerGas",
             Self::FeeHistory(_, _, _) => "eth_feeHistory",
+            Self::CreateAccessList(_, _) => "eth_createAccessList",
+            Self::Call(_, _) => "eth_call",
+            Self::Balance(_, _) => "eth_getBalance",
+            Self::BlockNumber => "eth_blockNumber",
+            Self::BlockByNumber(_, _) => "eth_getBlockByNumber",
+            Self::TransactionReceipt(_) => "eth_getTransactionReceipt",
+            Self::Logs(_) => "eth_getLogs",
+            Self::EstimateGas(_, _) => "eth_estimateGas",
+            Self::ChainId => "eth_chainId",
+            Self::Code(_, _) => "eth_getCode",
+            Self::StorageAt(_, _, _) => "eth_getStorageAt",
+            Self::Proof(_, _, _) => "eth_getProof",
         }
     }
     pub fn params(&self) -> Vec<Value> {
@@ -30,11 +103,86 @@ impl EVMRpcMethod {
                     helpers::serialize(&reward_percentiles),
                 ]
             }
-            EVMRpcMethod::TransactionCount(address, block_number) => vec![
+            EVMRpcMethod::TransactionCount(address, block_number)
+            | EVMRpcMethod::Balance(address, block_number)
+            | EVMRpcMethod::Code(address, block_number) => vec![
+                helpers::serialize(&address),
+                helpers::serialize(&block_number),
+            ],
+            EVMRpcMethod::CreateAccessList(request, block_number)
+            | EVMRpcMethod::Call(request, block_number) => vec![
+                helpers::serialize(&request),
+                helpers::serialize(&block_number),
+            ],
+            EVMRpcMethod::BlockByNumber(block_number, full_transactions) => vec![
+                helpers::serialize(&block_number),
+                helpers::serialize(&full_transactions),
+            ],
+            EVMRpcMethod::TransactionReceipt(hash) => vec![helpers::serialize(&hash)],
+            EVMRpcMethod::Logs(filter) => vec![helpers::serialize(&filter)],
+            // The block is optional, and not every node accepts it.
+            EVMRpcMethod::EstimateGas(request, block_number) => {
+                let mut params = vec![helpers::serialize(&request)];
+                if let Some(block_number) = block_number {
+                    params.push(helpers::serialize(&block_number));
+                }
+                params
+            }
+            EVMRpcMethod::StorageAt(address, slot, block_number) => vec![
+                helpers::serialize(&address),
+                helpers::serialize(&slot),
+                helpers::serialize(&block_number),
+            ],
+            EVMRpcMethod::Proof(address, keys, block_number) => vec![
                 helpers::serialize(&address),
+                helpers::serialize(&keys),
                 helpers::serialize(&block_number),
             ],
-            _ => vec![],
+            EVMRpcMethod::GasPrice
+            | EVMRpcMethod::MaxPriorityFeePerGas
+            | EVMRpcMethod::BlockNumber
+            | EVMRpcMethod::ChainId => vec![],
         }
     }
+
+    /// Decodes the result of this method.
+    pub fn decode(&self, value: Value) -> Result<EVMRpcResponse, ic_web3_rs::Error> {
+        use serde_json::from_value;
+        Ok(match self {
+            Self::TransactionCount(_, _) => EVMRpcResponse::TransactionCount(from_value(value)?),
+            Self::GasPrice => EVMRpcResponse::GasPrice(from_value(value)?),
+            Self::FeeHistory(_, _, _) => EVMRpcResponse::FeeHistory(from_value(value)?),
+            Self::MaxPriorityFeePerGas => EVMRpcResponse::MaxPriorityFeePerGas(from_value(value)?),
+            Self::CreateAccessList(_, _) => EVMRpcResponse::CreateAccessList(from_value(value)?),
+            Self::Call(_, _) => EVMRpcResponse::Call(from_value(value)?),
+            Self::Balance(_, _) => EVMRpcResponse::Balance(from_value(value)?),
+            Self::BlockNumber => EVMRpcResponse::BlockNumber(from_value(value)?),
+            Self::BlockByNumber(_, false) => EVMRpcResponse::BlockByNumber(from_value(value)?),
+            Self::BlockByNumber(_, true) => {
+                EVMRpcResponse::BlockWithTransactions(from_value(value)?)
+            }
+            Self::TransactionReceipt(_) => EVMRpcResponse::TransactionReceipt(from_value(value)?),
+            Self::Logs(_) => EVMRpcResponse::Logs(from_value(value)?),
+            Self::EstimateGas(_, _) => EVMRpcResponse::EstimateGas(from_value(value)?),
+            Self::ChainId => EVMRpcResponse::ChainId(from_value(value)?),
+            Self::Code(_, _) => EVMRpcResponse::Code(from_value(value)?),
+            Self::StorageAt(_, _, _) => EVMRpcResponse::StorageAt(from_value(value)?),
+            Self::Proof(_, _, _) => EVMRpcResponse::Proof(from_value(value)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storage_keys_are_32_bytes() {
+        let key = H256::from_low_u64_be(1);
+        let slot = format!("0x{}1", "0".repeat(63));
+        let proof = EVMRpcMethod::Proof(Address::zero(), vec![key], BlockNumber::Latest);
+        assert_eq!(proof.params()[1], Value::from(vec![slot.clone()]));
+        let storage = EVMRpcMethod::StorageAt(Address::zero(), key, BlockNumber::Latest);
+        assert_eq!(storage.params()[1], Value::from(slot));
+    }
 }
@@ -0,0 +1,253 @@
+//! Classification of the errors returned by `eth_sendRawTransaction`.
+
+use ic_web3_rs::types::H256;
+use serde_json::Value;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendErrorKind {
+    /// The nonce has already been used by a mined transaction.
+    NonceTooLow,
+    /// Another transaction with the same nonce is pending, and this one does
+    /// not pay enough more to replace it.
+    ReplacementUnderpriced,
+    /// The balance does not cover `gas * price + value`.
+    InsufficientFunds,
+    /// The node already has the transaction.
+    AlreadyKnown,
+}
+
+/// The JSON-RPC error codes nodes use when they reject a transaction: -32000
+/// by Geth, Erigon and Besu, -32003 as specified by EIP-1474, and -32010 by
+/// Nethermind and OpenEthereum. Errors with other codes, like 3 for reverts,
+/// are never classified.
+const SEND_ERROR_CODES: &[i64] = &[-32000, -32003, -32010];
+
+/// Message fragments of each kind, as reported by Geth, Erigon, Nethermind,
+/// Besu and OpenEthereum. They are compared after [`normalize`], so that
+/// `"NONCE_TOO_LOW"`, `"NonceTooLow"` and `"nonce too low"` are the same.
+const PATTERNS: &[(SendErrorKind, &[&str])] = &[
+    (
+        SendErrorKind::NonceTooLow,
+        &["noncetoolow", "oldnonce", "nonceistoolow"],
+    ),
+    (
+        SendErrorKind::ReplacementUnderpriced,
+        &[
+            "replacementtransactionunderpriced",
+            "replacementunderpriced",
+            "replacementnotallowed",
+            "feetoolowtocompete",
+            "couldnotreplaceexistingtx",
+            "anothertransactionwithsamenonce",
+        ],
+    ),
+    (
+        SendErrorKind::InsufficientFunds,
+        &[
+            "insufficientfunds",
+            "upfrontcostexceedsaccountbalance",
+            "transactionupfrontcostexceedsbalance",
+        ],
+    ),
+];
+
+/// The complete messages, without the hash some nodes append, which say that
+/// the node already has the transaction. As these turn a failed broadcast
+/// into a success, they must match exactly.
+const ALREADY_KNOWN: &[&str] = &[
+    "alreadyknown",
+    "knowntransaction",
+    "transactionalreadyknown",
+    "transactionalreadyimported",
+    "transactionwiththesamehashwasalreadyimported",
+];
+
+/// Returns the kind of `error` if it is one of the well known reasons for
+/// `eth_sendRawTransaction` to reject a transaction.
+pub fn send_error_kind(error: &ic_web3_rs::Error) -> Option<SendErrorKind> {
+    let texts = error_texts(error)?
+        .iter()
+        .map(|text| normalize(text))
+        .collect::<Vec<_>>();
+    // A revert reason can say anything, like "TokenAlreadyExists".
+    if texts.iter().any(|text| text.contains("revert")) {
+        return None;
+    }
+    if texts
+        .iter()
+        .any(|text| ALREADY_KNOWN.contains(&text.as_str()))
+    {
+        return Some(SendErrorKind::AlreadyKnown);
+    }
+    PATTERNS
+        .iter()
+        .find(|(_, patterns)| {
+            patterns
+                .iter()
+                .any(|p| texts.iter().any(|text| text.contains(p)))
+        })
+        .map(|(kind, _)| *kind)
+}
+
+/// Whether `error` says that the node already has the transaction `hash`.
+/// Nodes which include a hash in the error, like `known transaction: 0x...`,
+/// must name `hash`. Otherwise the node knows a transaction with the same raw
+/// bytes, which has the same hash.
+pub(crate) fn is_already_known(error: &ic_web3_rs::Error, hash: H256) -> bool {
+    if send_error_kind(error) != Some(SendErrorKind::AlreadyKnown) {
+        return false;
+    }
+    match error_hash(error) {
+        Some(known) => known == hash,
+        None => true,
+    }
+}
+
+/// The message, and the data if it is a string, of a transaction rejection.
+/// Some clients put the reason in `data` and a generic message, like
+/// "Transaction rejected", in `message`.
+fn error_texts(error: &ic_web3_rs::Error) -> Option<Vec<String>> {
+    match error {
+        ic_web3_rs::Error::Rpc(error) if SEND_ERROR_CODES.contains(&error.code.code()) => {
+            let mut texts = vec![error.message.clone()];
+            if let Some(Value::String(data)) = &error.data {
+                texts.push(data.clone());
+            }
+            Some(texts)
+        }
+        ic_web3_rs::Error::Transport(ic_web3_rs::error::TransportError::Message(message)) => {
+            Some(vec![message.clone()])
+        }
+        _ => None,
+    }
+}
+
+fn error_hash(error: &ic_web3_rs::Error) -> Option<H256> {
+    error_texts(error)?
+        .iter()
+        .find_map(|text| hex_words(text).find_map(|word| H256::from_str(&word[2..]).ok()))
+}
+
+fn hex_words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| word.starts_with("0x"))
+}
+
+/// Lowercases `message` and drops hex values and everything but letters, so
+/// that the different spellings of the same reason compare equal.
+fn normalize(message: &str) -> String {
+    message
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.starts_with("0x"))
+        .flat_map(str::chars)
+        .filter(char::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonrpc_core::{Error as RpcError, ErrorCode};
+
+    fn rpc_error(code: i64, message: &str) -> ic_web3_rs::Error {
+        ic_web3_rs::Error::Rpc(RpcError {
+            code: ErrorCode::from(code),
+            message: message.to_string(),
+            data: None,
+        })
+    }
+
+    #[test]
+    fn classifies_client_messages() {
+        let cases = [
+            ("nonce too low", SendErrorKind::NonceTooLow),
+            ("NONCE_TOO_LOW", SendErrorKind::NonceTooLow),
+            ("OldNonce", SendErrorKind::NonceTooLow),
+            (
+                "Transaction nonce is too low. Try incrementing the nonce.",
+                SendErrorKind::NonceTooLow,
+            ),
+            (
+                "replacement transaction underpriced",
+                SendErrorKind::ReplacementUnderpriced,
+            ),
+            (
+                "REPLACEMENT_UNDERPRICED",
+                SendErrorKind::ReplacementUnderpriced,
+            ),
+            ("FeeTooLowToCompete", SendErrorKind::ReplacementUnderpriced),
+            (
+                "insufficient funds for gas * price + value",
+                SendErrorKind::InsufficientFunds,
+            ),
+            (
+                "TRANSACTION_UPFRONT_COST_EXCEEDS_BALANCE",
+                SendErrorKind::InsufficientFunds,
+            ),
+            ("already known", SendErrorKind::AlreadyKnown),
+            ("AlreadyKnown", SendErrorKind::AlreadyKnown),
+            ("Known transaction", SendErrorKind::AlreadyKnown),
+            (
+                "Transaction with the same hash was already imported.",
+                SendErrorKind::AlreadyKnown,
+            ),
+        ];
+        for (message, kind) in cases {
+            for code in SEND_ERROR_CODES {
+                assert_eq!(
+                    send_error_kind(&rpc_error(*code, message)),
+                    Some(kind),
+                    "{}",
+                    message
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ignores_other_errors() {
+        for message in [
+            "execution reverted: TokenAlreadyExists",
+            "execution reverted: InsufficientFunds",
+            "unknown transaction type",
+            "transaction already exists in the pool of another chain",
+            "intrinsic gas too low",
+        ] {
+            assert_eq!(
+                send_error_kind(&rpc_error(-32000, message)),
+                None,
+                "{}",
+                message
+            );
+        }
+        // Reverts use their own code.
+        assert_eq!(send_error_kind(&rpc_error(3, "nonce too low")), None);
+        assert_eq!(send_error_kind(&rpc_error(-32601, "already known")), None);
+    }
+
+    #[test]
+    fn already_known_must_name_the_hash() {
+        let hash = H256::repeat_byte(0xab);
+        let other = H256::repeat_byte(0xcd);
+        assert!(is_already_known(&rpc_error(-32000, "already known"), hash));
+        let known = |hash: H256| rpc_error(-32010, &format!("known transaction: {:?}", hash));
+        assert!(is_already_known(&known(hash), hash));
+        assert!(!is_already_known(&known(other), hash));
+        assert!(!is_already_known(
+            &rpc_error(-32000, "execution reverted: TokenAlreadyExists"),
+            hash
+        ));
+    }
+
+    #[test]
+    fn reads_the_reason_from_data() {
+        let error = ic_web3_rs::Error::Rpc(RpcError {
+            code: ErrorCode::from(-32010),
+            message: "Transaction rejected".to_string(),
+            data: Some(Value::String("AlreadyKnown".to_string())),
+        });
+        assert_eq!(send_error_kind(&error), Some(SendErrorKind::AlreadyKnown));
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{
+    context::{Context, Web3Context},
+    providers::{CallProvider, LogProvider, SendProvider},
+    types::{EventLogs, SignedTransaction},
+    Web3Provider,
+};
+use async_trait::async_trait;
+use ic_web3_rs::{
+    contract::{
+        tokens::{Detokenize, Tokenize},
+        Options,
+    },
+    ethabi::TopicFilter,
+    transports::ic_http_client::CallOptions,
+    types::Address,
+};
+
+/// A context for contracts whose send methods sign transactions without
+/// broadcasting them. See [`Web3Context::sign_only`].
+#[derive(Clone)]
+pub struct SignOnlyContext(Web3Context);
+
+impl Context for SignOnlyContext {
+    type Provider = SignOnlyProvider;
+    fn provider(&self, contract: Address, json_abi: &[u8]) -> Self::Provider {
+        SignOnlyProvider(Web3Provider::new(contract, &self.0, json_abi))
+    }
+}
+
+impl Web3Context {
+    /// Contracts created with the returned context return the
+    /// [`SignedTransaction`] from their send methods, so that it can be
+    /// broadcast later with [`Web3Context::send_raw_transaction`].
+    pub fn sign_only(&self) -> SignOnlyContext {
+        SignOnlyContext(self.clone())
+    }
+}
+
+pub struct SignOnlyProvider(Web3Provider);
+
+impl SignOnlyProvider {
+    pub fn inner(&self) -> &Web3Provider {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl SendProvider for SignOnlyProvider {
+    type Out = SignedTransaction;
+    async fn send<Params: Tokenize + Send>(
+        &self,
+        func: &'static str,
+        params: Params,
+        options: Option<Options>,
+    ) -> Result<Self::Out, ic_web3_rs::Error> {
+        self.0.sign(func, params, options).await
+    }
+}
+
+#[async_trait]
+impl CallProvider for SignOnlyProvider {
+    async fn call<O: Detokenize + Unpin + Send, Params: Tokenize + Send>(
+        &self,
+        name: &'static str,
+        params: Params,
+    ) -> Result<O, ic_web3_rs::Error> {
+        self.0.call(name, params).await
+    }
+}
+
+#[async_trait]
+impl LogProvider for SignOnlyProvider {
+    async fn find(
+        &self,
+        event_name: &str,
+        from: u64,
+        to: u64,
+        call_options: CallOptions,
+    ) -> Result<EventLogs, ic_web3_rs::Error> {
+        self.0.find(event_name, from, to, call_options).await
+    }
+
+    async fn find_anonymous(
+        &self,
+        event_name: &str,
+        from: u64,
+        to: u64,
+        topics: TopicFilter,
+        call_options: CallOptions,
+    ) -> Result<EventLogs, ic_web3_rs::Error> {
+        self.0
+            .find_anonymous(event_name, from, to, topics, call_options)
+            .await
+    }
+}
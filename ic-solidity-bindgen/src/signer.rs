@@ -0,0 +1,72 @@
+use crate::web3_provider::default_derivation_key;
+use ic_web3_rs::error::TransportError;
+use ic_web3_rs::ic::{get_public_key, pubkey_to_address, KeyInfo};
+use ic_web3_rs::types::Address;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Key name and derivation path, which together identify a tECDSA key.
+type KeyId = (String, Vec<Vec<u8>>);
+
+thread_local! {
+    // The public key of a derivation path never changes, so there is no need
+    // to ask the management canister more than once.
+    static ADDRESSES: RefCell<HashMap<KeyId, Address>> = RefCell::new(HashMap::new());
+}
+
+/// The threshold ECDSA key used to sign transactions.
+///
+/// Each derivation path yields a distinct key, and so a distinct EVM account.
+/// For example, a canister can hold one account per user by deriving with
+/// `vec![user.as_slice().to_vec()]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signer {
+    pub derivation_path: Vec<Vec<u8>>,
+    pub key_name: String,
+    /// Cycles attached to `sign_with_ecdsa`. The ic-web3-rs default is used
+    /// when this is `None`.
+    pub ecdsa_sign_cycles: Option<u64>,
+}
+
+impl Signer {
+    /// A signer for the canister's own account, derived from its principal.
+    pub fn new(key_name: impl Into<String>) -> Self {
+        Self {
+            derivation_path: vec![default_derivation_key()],
+            key_name: key_name.into(),
+            ecdsa_sign_cycles: None,
+        }
+    }
+
+    pub fn with_derivation_path(mut self, derivation_path: Vec<Vec<u8>>) -> Self {
+        self.derivation_path = derivation_path;
+        self
+    }
+
+    pub fn with_ecdsa_sign_cycles(mut self, cycles: u64) -> Self {
+        self.ecdsa_sign_cycles = Some(cycles);
+        self
+    }
+
+    /// The EVM address of this key, derived from its tECDSA public key.
+    pub async fn address(&self) -> Result<Address, ic_web3_rs::Error> {
+        let cache_key = (self.key_name.clone(), self.derivation_path.clone());
+        if let Some(address) = ADDRESSES.with(|a| a.borrow().get(&cache_key).copied()) {
+            return Ok(address);
+        }
+        let public_key = get_public_key(None, self.derivation_path.clone(), self.key_name.clone())
+            .await
+            .map_err(|e| ic_web3_rs::Error::Transport(TransportError::Message(e)))?;
+        let address = pubkey_to_address(&public_key).map_err(ic_web3_rs::Error::Decoder)?;
+        ADDRESSES.with(|a| a.borrow_mut().insert(cache_key, address));
+        Ok(address)
+    }
+
+    pub fn key_info(&self) -> KeyInfo {
+        KeyInfo {
+            derivation_path: self.derivation_path.clone(),
+            key_name: self.key_name.clone(),
+            ecdsa_sign_cycles: self.ecdsa_sign_cycles,
+        }
+    }
+}
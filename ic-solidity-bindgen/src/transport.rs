@@ -0,0 +1,312 @@
+//! The HTTP outcall transport used when a context needs more control over
+//! the requests than ic-web3-rs' `ICHttp` gives: extra headers such as an API
+//! key, a fixed amount of cycles per request and the transform function.
+
+use ic_cdk::api::management_canister::http_request::{
+    http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod, TransformContext,
+};
+use ic_web3_rs::{
+    error::{Error, Result, TransportError},
+    helpers,
+    transports::{ic_http_client::CallOptions, Either, ICHttp},
+    BatchTransport, RequestId, Transport,
+};
+use jsonrpc_core::types::{Call, Output, Request, Value};
+use serde::de::DeserializeOwned;
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// The transport of a [`crate::Web3Context`]. Contexts built without any of
+/// the outcall settings of [`crate::Web3ContextBuilder`] use `ICHttp`.
+pub(crate) type ContextTransport = Either<ICHttp, OutcallTransport>;
+
+/// The same default as `ICHttp`.
+const DEFAULT_MAX_RESPONSE_BYTES: u64 = 500_000;
+/// The name of the transform function `ICHttp` uses.
+const DEFAULT_TRANSFORM: &str = "transform";
+/// The cycles are estimated for a 13 node subnet, like `ICHttp` does.
+const SUBNET_SIZE: u128 = 13;
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
+#[derive(Clone, Debug)]
+pub(crate) struct OutcallTransport {
+    inner: Arc<Outcall>,
+    max_response_bytes: u64,
+}
+
+#[derive(Debug)]
+struct Outcall {
+    url: String,
+    headers: Vec<HttpHeader>,
+    cycles: Option<u128>,
+    transform: String,
+    id: AtomicUsize,
+}
+
+impl OutcallTransport {
+    /// `headers` are sent with every request, after a JSON `Content-Type`
+    /// unless they set their own.
+    pub(crate) fn new(
+        url: &str,
+        headers: Vec<(String, String)>,
+        max_response_bytes: Option<u64>,
+        cycles: Option<u128>,
+        transform: Option<String>,
+    ) -> Self {
+        let mut headers: Vec<HttpHeader> = headers
+            .into_iter()
+            .map(|(name, value)| HttpHeader { name, value })
+            .collect();
+        if !headers
+            .iter()
+            .any(|h| h.name.eq_ignore_ascii_case("content-type"))
+        {
+            headers.insert(
+                0,
+                HttpHeader {
+                    name: "Content-Type".to_string(),
+                    value: "application/json".to_string(),
+                },
+            );
+        }
+        Self {
+            inner: Arc::new(Outcall {
+                url: url.to_string(),
+                headers,
+                cycles,
+                transform: transform.unwrap_or_else(|| DEFAULT_TRANSFORM.to_string()),
+                id: AtomicUsize::new(0),
+            }),
+            max_response_bytes: max_response_bytes.unwrap_or(DEFAULT_MAX_RESPONSE_BYTES),
+        }
+    }
+
+    fn next_id(&self) -> RequestId {
+        self.inner.id.fetch_add(1, Ordering::AcqRel)
+    }
+
+    fn argument(
+        &self,
+        body: Vec<u8>,
+        transform: Option<TransformContext>,
+    ) -> CanisterHttpRequestArgument {
+        CanisterHttpRequestArgument {
+            url: self.inner.url.clone(),
+            max_response_bytes: Some(self.max_response_bytes),
+            method: HttpMethod::POST,
+            headers: self.inner.headers.clone(),
+            body: Some(body),
+            transform,
+        }
+    }
+
+    fn execute<T: DeserializeOwned>(
+        &self,
+        request: Request,
+        options: CallOptions,
+    ) -> BoxFuture<Result<T>> {
+        let this = self.clone();
+        Box::pin(async move {
+            // The fields of `CallOptions` can't be read outside ic-web3-rs,
+            // so they can't be honoured here.
+            if options != CallOptions::default() {
+                return Err(transport_error(
+                    "CallOptions aren't supported by a context with outcall settings, \
+                     set them on the Web3ContextBuilder instead"
+                        .to_string(),
+                ));
+            }
+            let body = serde_json::to_vec(&request)
+                .map_err(|e| transport_error(format!("failed to serialize request: {}", e)))?;
+            let transform = TransformContext::from_name(this.inner.transform.clone(), vec![]);
+            let argument = this.argument(body, Some(transform));
+            let cycles = this
+                .inner
+                .cycles
+                .unwrap_or_else(|| required_cycles(&argument));
+            let (response,) = http_request(argument, cycles)
+                .await
+                .map_err(|(code, message)| {
+                    transport_error(format!(
+                        "The http_request resulted into error. RejectionCode: {:?}, Error: {}",
+                        code, message
+                    ))
+                })?;
+            helpers::arbitrary_precision_deserialize_workaround(&response.body).map_err(|e| {
+                transport_error(format!(
+                    "failed to deserialize response: {}: {}",
+                    e,
+                    String::from_utf8_lossy(&response.body)
+                ))
+            })
+        })
+    }
+}
+
+impl Transport for OutcallTransport {
+    type Out = BoxFuture<Result<Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        let id = self.next_id();
+        (id, helpers::build_request(id, method, params))
+    }
+
+    fn send(&self, _id: RequestId, call: Call, options: CallOptions) -> Self::Out {
+        let output = self.execute::<Output>(Request::Single(call), options);
+        Box::pin(async move { helpers::to_result_from_output(output.await?) })
+    }
+
+    fn set_max_response_bytes(&mut self, v: u64) {
+        self.max_response_bytes = v;
+    }
+}
+
+impl BatchTransport for OutcallTransport {
+    type Batch = BoxFuture<Result<Vec<Result<Value>>>>;
+
+    fn send_batch<T>(&self, requests: T) -> Self::Batch
+    where
+        T: IntoIterator<Item = (RequestId, Call)>,
+    {
+        let (ids, calls): (Vec<_>, Vec<_>) = requests.into_iter().unzip();
+        let outputs = self.execute::<Vec<Output>>(Request::Batch(calls), CallOptions::default());
+        Box::pin(async move { reorder_batch(&ids, outputs.await?) })
+    }
+}
+
+fn transport_error(message: String) -> Error {
+    Error::Transport(TransportError::Message(message))
+}
+
+/// The cycles charged for an outcall, see
+/// https://internetcomputer.org/docs/current/developer-docs/gas-cost.
+fn required_cycles(argument: &CanisterHttpRequestArgument) -> u128 {
+    let request_bytes = argument.url.len()
+        + argument
+            .headers
+            .iter()
+            .map(|h| h.name.len() + h.value.len())
+            .sum::<usize>()
+        + argument.body.as_ref().map_or(0, Vec::len)
+        + argument
+            .transform
+            .as_ref()
+            .map_or(0, |t| t.function.0.method.len() + t.context.len());
+    let response_bytes = argument.max_response_bytes.unwrap_or(2 * 1024 * 1024) as u128;
+    (3_000_000 + 60_000 * SUBNET_SIZE + 400 * request_bytes as u128 + 800 * response_bytes)
+        * SUBNET_SIZE
+}
+
+// Batch responses can come in any order, so they are matched to the requests
+// by id.
+fn reorder_batch(ids: &[RequestId], outputs: Vec<Output>) -> Result<Vec<Result<Value>>> {
+    if ids.len() != outputs.len() {
+        return Err(Error::InvalidResponse(
+            "unexpected number of responses".to_string(),
+        ));
+    }
+    let mut outputs = outputs
+        .into_iter()
+        .map(|output| {
+            let id = match output {
+                Output::Success(ref success) => &success.id,
+                Output::Failure(ref failure) => &failure.id,
+            };
+            let id = match id {
+                jsonrpc_core::Id::Num(id) => *id as RequestId,
+                _ => {
+                    return Err(Error::InvalidResponse(
+                        "response id is not a number".to_string(),
+                    ))
+                }
+            };
+            Ok((id, helpers::to_result_from_output(output)))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+    ids.iter()
+        .map(|id| {
+            outputs.remove(id).ok_or_else(|| {
+                Error::InvalidResponse(format!("batch response is missing id {}", id))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_keep_a_json_content_type() {
+        let transport = OutcallTransport::new(
+            "https://rpc.example",
+            vec![("x-api-key".to_string(), "secret".to_string())],
+            None,
+            None,
+            None,
+        );
+        let names: Vec<_> = transport
+            .inner
+            .headers
+            .iter()
+            .map(|h| h.name.as_str())
+            .collect();
+        assert_eq!(names, ["Content-Type", "x-api-key"]);
+
+        let transport = OutcallTransport::new(
+            "https://rpc.example",
+            vec![(
+                "content-type".to_string(),
+                "application/json-rpc".to_string(),
+            )],
+            None,
+            None,
+            None,
+        );
+        assert_eq!(transport.inner.headers.len(), 1);
+        assert_eq!(transport.inner.headers[0].value, "application/json-rpc");
+    }
+
+    #[test]
+    fn cycles_grow_with_the_request() {
+        let argument = |headers: Vec<(String, String)>| {
+            OutcallTransport::new("https://rpc.example", headers, None, None, None)
+                .argument(b"{}".to_vec(), None)
+        };
+        let plain = required_cycles(&argument(vec![]));
+        let with_key = required_cycles(&argument(vec![(
+            "x-api-key".to_string(),
+            "secret".to_string(),
+        )]));
+        assert_eq!(with_key - plain, 400 * SUBNET_SIZE * 15);
+    }
+
+    #[test]
+    fn batch_responses_are_reordered() {
+        let outputs = [1u64, 0, 2]
+            .iter()
+            .map(|&id| {
+                Output::Success(jsonrpc_core::Success {
+                    jsonrpc: None,
+                    result: id.into(),
+                    id: jsonrpc_core::Id::Num(id),
+                })
+            })
+            .collect();
+        let results: Vec<_> = reorder_batch(&[0, 1, 2], outputs)
+            .unwrap()
+            .into_iter()
+            .map(|result| result.unwrap().as_u64().unwrap())
+            .collect();
+        assert_eq!(results, [0, 1, 2]);
+        assert!(reorder_batch(&[0, 1], vec![]).is_err());
+    }
+}
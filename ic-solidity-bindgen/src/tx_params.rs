@@ -0,0 +1,199 @@
+//! The fee and nonce parameters of the transactions a context signs.
+
+use crate::{context::Web3Context, rpc_methods::EVMRpcMethod};
+use ic_web3_rs::{
+    contract::Options,
+    types::{BlockId, BlockNumber, FeeHistory, U256, U64},
+};
+use std::future::Future;
+
+impl Web3Context {
+    pub(crate) async fn tx_options(
+        &self,
+        options: Option<Options>,
+    ) -> Result<Options, ic_web3_rs::Error> {
+        match options {
+            Some(options) if options.gas_price.is_some() || options.max_fee_per_gas.is_some() => {
+                Ok(options)
+            }
+            // Options without fees, for example those only carrying the value
+            // of a payable call, get the default fees.
+            Some(options) => {
+                let fees = self.build_default_tx_params().await?;
+                Ok(Options {
+                    gas_price: fees.gas_price,
+                    max_fee_per_gas: fees.max_fee_per_gas,
+                    max_priority_fee_per_gas: options
+                        .max_priority_fee_per_gas
+                        .or(fees.max_priority_fee_per_gas),
+                    nonce: options.nonce.or(fees.nonce),
+                    transaction_type: options.transaction_type.or(fees.transaction_type),
+                    ..options
+                })
+            }
+            None => self.build_default_tx_params().await,
+        }
+    }
+
+    /// EIP-1559 params on chains which support them, legacy params otherwise.
+    pub async fn build_default_tx_params(&self) -> Result<Options, ic_web3_rs::Error> {
+        if self.supports_eip1559().await? {
+            self.build_eip_1559_tx_params().await
+        } else {
+            self.build_legacy_tx_params_with_batch().await
+        }
+    }
+
+    pub async fn build_eip_1559_tx_params(&self) -> Result<Options, ic_web3_rs::Error> {
+        let eth = self.eth();
+        let current_block = self
+            .with_retry(|| eth.block(BlockId::Number(BlockNumber::Latest), self.call_options()))
+            .await?;
+        if current_block.is_none() {
+            return Err(ic_web3_rs::Error::InvalidResponse(
+                "No block returned".to_string(),
+            ));
+        }
+        let current_block = current_block.unwrap();
+        self._build_eip_1559_tx_params(current_block.base_fee_per_gas.unwrap_or_default())
+            .await
+    }
+
+    pub async fn build_eip_1559_tx_params_with_fee_history(
+        &self,
+    ) -> Result<Options, ic_web3_rs::Error> {
+        let eth = self.eth();
+        let fee_history = self
+            .with_retry(|| {
+                eth.fee_history(U256::one(), BlockNumber::Latest, None, self.call_options())
+            })
+            .await?;
+        self._build_eip_1559_tx_params(
+            fee_history
+                .base_fee_per_gas
+                .first()
+                .copied()
+                .unwrap_or_default(),
+        )
+        .await
+    }
+
+    pub async fn build_eip_1559_tx_params_with_batch(&self) -> Result<Options, ic_web3_rs::Error> {
+        let requests = vec![
+            EVMRpcMethod::FeeHistory(U256::one(), BlockNumber::Latest, None),
+            EVMRpcMethod::MaxPriorityFeePerGas,
+            EVMRpcMethod::TransactionCount(self.from(), BlockNumber::Latest),
+        ];
+        let resp = self.batch_call(&requests).await?;
+
+        let (ok, err) = resp.into_iter().partition::<Vec<_>, _>(Result::is_ok);
+        if !err.is_empty() {
+            return Err(ic_web3_rs::error::Error::InvalidResponse(format!(
+                "Some method failed: {err:?}"
+            )));
+        }
+        if ok.len() != requests.len() {
+            return Err(ic_web3_rs::error::Error::InvalidResponse(format!(
+                "Some method not responded. response={ok:?}"
+            )));
+        }
+
+        let mut ok = ok.into_iter().filter_map(Result::ok).collect::<Vec<_>>();
+        let fee_history: FeeHistory = serde_json::from_value(ok.remove(0))?;
+        let base_fee_per_gas = fee_history
+            .base_fee_per_gas
+            .first()
+            .copied()
+            .unwrap_or_default();
+        let max_priority_fee_per_gas: U256 = serde_json::from_value(ok.remove(0))?;
+        let nonce = serde_json::from_value(ok.remove(0))?;
+
+        Ok(Options {
+            max_fee_per_gas: Some(calc_max_fee_per_gas(
+                max_priority_fee_per_gas,
+                base_fee_per_gas,
+            )),
+            max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
+            nonce: Some(nonce),
+            transaction_type: Some(U64::from(2)), // EIP1559_TX_ID for default
+            ..Default::default()
+        })
+    }
+
+    pub async fn build_legacy_tx_params_with_batch(&self) -> Result<Options, ic_web3_rs::Error> {
+        let requests = vec![
+            EVMRpcMethod::GasPrice,
+            EVMRpcMethod::TransactionCount(self.from(), BlockNumber::Latest),
+        ];
+        let resp = self.batch_call(&requests).await?;
+
+        let (ok, err) = resp.into_iter().partition::<Vec<_>, _>(Result::is_ok);
+        if !err.is_empty() {
+            return Err(ic_web3_rs::error::Error::InvalidResponse(format!(
+                "Some method failed: {err:?}"
+            )));
+        }
+        if ok.len() != requests.len() {
+            return Err(ic_web3_rs::error::Error::InvalidResponse(format!(
+                "Some method not responded. response={ok:?}"
+            )));
+        }
+
+        let mut ok = ok.into_iter().filter_map(Result::ok).collect::<Vec<_>>();
+        let gas_price: U256 = serde_json::from_value(ok.remove(0))?;
+        let nonce = serde_json::from_value(ok.remove(0))?;
+
+        Ok(Options {
+            gas_price: Some(gas_price),
+            nonce: Some(nonce),
+            ..Default::default()
+        })
+    }
+
+    async fn _build_eip_1559_tx_params(
+        &self,
+        base_fee_per_gas: U256,
+    ) -> Result<Options, ic_web3_rs::Error> {
+        let eth = self.eth();
+        let max_priority_fee_per_gas = self
+            .with_retry(|| eth.max_priority_fee_per_gas(self.call_options()))
+            .await?;
+        let nonce = self
+            .with_retry(|| eth.transaction_count(self.from(), None, self.call_options()))
+            .await?;
+
+        Ok(Options {
+            max_fee_per_gas: Some(calc_max_fee_per_gas(
+                max_priority_fee_per_gas,
+                base_fee_per_gas,
+            )),
+            max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
+            nonce: Some(nonce),
+            transaction_type: Some(U64::from(2)), // EIP1559_TX_ID for default
+            ..Default::default()
+        })
+    }
+
+    async fn with_retry<T, E, Fut, F: FnMut() -> Fut>(&self, mut f: F) -> Result<T, E>
+    where
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut count = 0;
+        loop {
+            let result = f().await;
+
+            if result.is_ok() {
+                break result;
+            } else {
+                if count > self.max_retry() {
+                    break result;
+                }
+                count += 1;
+            }
+        }
+    }
+}
+
+fn calc_max_fee_per_gas(max_priority_fee_per_gas: U256, base_fee_per_gas: U256) -> U256 {
+    max_priority_fee_per_gas + (base_fee_per_gas * U256::from(2))
+}
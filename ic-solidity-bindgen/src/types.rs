@@ -1,8 +1,128 @@
+use ic_web3_rs::contract::Options;
 use ic_web3_rs::ethabi::Log;
-use ic_web3_rs::types::Log as EthLog;
+use ic_web3_rs::types::{AccessList, Address, Bytes, Log as EthLog, H256, U256, U64};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct EventLog {
     pub event: Log,
     pub log: EthLog,
 }
+
+/// Why a log returned by the node could not be turned into an [`EventLog`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LogDecodeErrorKind {
+    /// The topics or data did not match the event in the ABI.
+    Abi(String),
+    /// The node returned a mined log without a block number.
+    MissingBlockNumber,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LogDecodeError {
+    pub kind: LogDecodeErrorKind,
+    pub log: EthLog,
+}
+
+/// The result of a log query. Logs are grouped by block number, and logs which
+/// could not be decoded are reported in `errors` instead of failing the query.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct EventLogs {
+    pub events: HashMap<u64, Vec<EventLog>>,
+    pub errors: Vec<LogDecodeError>,
+}
+
+/// A secp256k1 signature with `v` in the Ethereum notation of 27 or 28.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Signature {
+    pub r: H256,
+    pub s: H256,
+    pub v: u8,
+}
+
+impl Signature {
+    /// The 65 bytes `r ‖ s ‖ v` used by `ecrecover` based verifiers.
+    pub fn to_bytes(&self) -> [u8; 65] {
+        let mut bytes = [0u8; 65];
+        bytes[..32].copy_from_slice(self.r.as_bytes());
+        bytes[32..64].copy_from_slice(self.s.as_bytes());
+        bytes[64] = self.v;
+        bytes
+    }
+}
+
+/// A signed transaction together with the parameters it was signed with, so
+/// that it can be stored, inspected, broadcast or replaced later.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SignedTransaction {
+    /// The RLP encoded transaction, ready for `eth_sendRawTransaction`.
+    pub raw_transaction: Bytes,
+    pub transaction_hash: H256,
+    pub from: Address,
+    pub to: Address,
+    pub data: Bytes,
+    pub value: U256,
+    pub nonce: U256,
+    pub gas: U256,
+    pub gas_price: Option<U256>,
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub transaction_type: Option<U64>,
+    pub access_list: Option<AccessList>,
+}
+
+impl SignedTransaction {
+    /// The options which reproduce this transaction when signed again.
+    pub fn options(&self) -> Options {
+        Options {
+            gas: Some(self.gas),
+            gas_price: self.gas_price,
+            value: Some(self.value),
+            nonce: Some(self.nonce),
+            transaction_type: self.transaction_type,
+            access_list: self.access_list.clone(),
+            max_fee_per_gas: self.max_fee_per_gas,
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas,
+            ..Default::default()
+        }
+    }
+}
+
+/// Every transaction signed for the same nonce. They replace each other in
+/// the mempool, so at most one of them is ever mined.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PendingTransaction {
+    // Never empty, there is always at least the original transaction.
+    attempts: Vec<SignedTransaction>,
+}
+
+impl PendingTransaction {
+    /// Every attempt, oldest first.
+    pub fn attempts(&self) -> &[SignedTransaction] {
+        &self.attempts
+    }
+
+    pub(crate) fn push(&mut self, tx: SignedTransaction) {
+        self.attempts.push(tx);
+    }
+
+    pub fn nonce(&self) -> U256 {
+        self.latest().nonce
+    }
+
+    /// The most recent, and so best priced, attempt.
+    pub fn latest(&self) -> &SignedTransaction {
+        self.attempts.last().unwrap()
+    }
+
+    pub fn hashes(&self) -> Vec<H256> {
+        self.attempts.iter().map(|tx| tx.transaction_hash).collect()
+    }
+}
+
+impl From<SignedTransaction> for PendingTransaction {
+    fn from(tx: SignedTransaction) -> Self {
+        Self { attempts: vec![tx] }
+    }
+}
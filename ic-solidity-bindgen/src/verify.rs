@@ -0,0 +1,63 @@
+//! Checks that a context is connected to the intended chain and that
+//! contracts are deployed where they are expected.
+
+use crate::context::Web3Context;
+use ic_web3_rs::{
+    signing::keccak256,
+    transports::ic_http_client::CallOptions,
+    types::{Address, H256, U256},
+};
+
+/// The hash [`Web3Context::verify_contract`] compares, for example of the
+/// `deployedBytecode` of a compiler artifact. Contracts with immutables have
+/// different code for every deployment, so their hash must come from the
+/// deployment itself.
+pub fn code_hash(code: &[u8]) -> H256 {
+    keccak256(code).into()
+}
+
+impl Web3Context {
+    /// Checks that the node's `eth_chainId` is the context's chain id, which
+    /// transactions are signed for.
+    pub async fn verify_chain_id(
+        &self,
+        call_options: CallOptions,
+    ) -> Result<(), ic_web3_rs::Error> {
+        let chain_id = self.eth().chain_id(call_options).await?;
+        if chain_id != U256::from(self.chain_id()) {
+            return Err(ic_web3_rs::Error::InvalidResponse(format!(
+                "the node is on chain {}, but the context is for chain {}",
+                chain_id,
+                self.chain_id()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks that there is code at `address`, and if `expected_code_hash` is
+    /// given, that it is the code with that hash.
+    pub async fn verify_contract(
+        &self,
+        address: Address,
+        expected_code_hash: Option<H256>,
+        call_options: CallOptions,
+    ) -> Result<(), ic_web3_rs::Error> {
+        let code = self.eth().code(address, None, call_options).await?;
+        if code.0.is_empty() {
+            return Err(ic_web3_rs::Error::InvalidResponse(format!(
+                "there is no contract at {:?}",
+                address
+            )));
+        }
+        if let Some(expected) = expected_code_hash {
+            let actual = code_hash(&code.0);
+            if actual != expected {
+                return Err(ic_web3_rs::Error::InvalidResponse(format!(
+                    "the code at {:?} has hash {:?}, expected {:?}",
+                    address, actual, expected
+                )));
+            }
+        }
+        Ok(())
+    }
+}
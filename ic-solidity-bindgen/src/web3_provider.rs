@@ -2,28 +2,26 @@ use crate::{
     context::Web3Context,
     providers::{CallProvider, LogProvider, SendProvider},
     rpc_methods::EVMRpcMethod,
-    types::EventLog,
+    transport::ContextTransport,
+    types::{EventLog, EventLogs, LogDecodeError, LogDecodeErrorKind, SignedTransaction},
 };
 use async_trait::async_trait;
 use ic_web3_rs::{
-    api::Namespace,
     contract::{
         tokens::{Detokenize, Tokenize},
         Contract, Options,
     },
-    ethabi::{RawLog, Topic, TopicFilter},
-    ic::KeyInfo,
-    transports::{ic_http_client::CallOptions, ICHttp},
-    types::{Address, BlockId, BlockNumber, FeeHistory, FilterBuilder, H256, U256, U64},
-    BatchTransport, Transport,
+    ethabi::{Event, RawLog, Topic, TopicFilter},
+    transports::ic_http_client::CallOptions,
+    types::{Address, BlockNumber, FilterBuilder, Log as EthLog, H256, U256},
 };
-use std::{collections::HashMap, future::Future, marker::Unpin};
+use std::marker::Unpin;
 
-const RPC_CALL_MAX_RETRY: u8 = 3;
+pub(crate) const RPC_CALL_MAX_RETRY: u8 = 3;
 /// Mostly exists to map to the new futures.
 /// This is the "untyped" API which the generated types will use.
 pub struct Web3Provider {
-    contract: Contract<ICHttp>,
+    contract: Contract<ContextTransport>,
     context: Web3Context,
     rpc_call_max_retry: u8,
 }
@@ -32,24 +30,6 @@ impl Web3Provider {
     pub fn contract(&self) -> ic_web3_rs::ethabi::Contract {
         self.contract.abi().clone()
     }
-    async fn with_retry<T, E, Fut, F: FnMut() -> Fut>(&self, mut f: F) -> Result<T, E>
-    where
-        Fut: Future<Output = Result<T, E>>,
-    {
-        let mut count = 0;
-        loop {
-            let result = f().await;
-
-            if result.is_ok() {
-                break result;
-            } else {
-                if count > self.rpc_call_max_retry {
-                    break result;
-                }
-                count += 1;
-            }
-        }
-    }
 }
 
 #[async_trait]
@@ -65,7 +45,10 @@ impl CallProvider for Web3Provider {
                 name,
                 params,
                 Some(self.context.from()),
-                Default::default(),
+                Options {
+                    call_options: Some(self.context.call_options()),
+                    ..Default::default()
+                },
                 None,
             )
             .await
@@ -93,14 +76,6 @@ pub fn default_derivation_key() -> Vec<u8> {
     ic_cdk::id().as_slice().to_vec()
 }
 
-fn event_sig<T: Transport>(contract: &Contract<T>, name: &str) -> Result<H256, String> {
-    contract
-        .abi()
-        .event(name)
-        .map(|e| e.signature())
-        .map_err(|e| (format!("event {} not found in contract abi: {}", name, e)))
-}
-
 #[async_trait]
 impl LogProvider for Web3Provider {
     async fn find(
@@ -109,12 +84,61 @@ impl LogProvider for Web3Provider {
         from: u64,
         to: u64,
         call_options: CallOptions,
-    ) -> Result<HashMap<u64, Vec<EventLog>>, ic_web3_rs::Error> {
-        let parser = self
-            .contract
-            .abi()
-            .event(event_name)
-            .map_err(|_| ic_web3_rs::Error::Internal)?;
+    ) -> Result<EventLogs, ic_web3_rs::Error> {
+        let parser = self.event(event_name)?;
+        // Anonymous events don't carry their signature in topic0, so the only
+        // thing left to filter on is the contract address.
+        let topic0 = if parser.anonymous {
+            Topic::Any
+        } else {
+            Topic::This(parser.signature())
+        };
+        let topics = TopicFilter {
+            topic0,
+            topic1: Topic::Any,
+            topic2: Topic::Any,
+            topic3: Topic::Any,
+        };
+        self.find_logs(parser, topics, from, to, call_options).await
+    }
+
+    async fn find_anonymous(
+        &self,
+        event_name: &str,
+        from: u64,
+        to: u64,
+        topics: TopicFilter,
+        call_options: CallOptions,
+    ) -> Result<EventLogs, ic_web3_rs::Error> {
+        let parser = self.event(event_name)?;
+        if !parser.anonymous {
+            return Err(ic_web3_rs::Error::Decoder(format!(
+                "event {} is not anonymous",
+                event_name
+            )));
+        }
+        self.find_logs(parser, topics, from, to, call_options).await
+    }
+}
+
+impl Web3Provider {
+    fn event(&self, event_name: &str) -> Result<&Event, ic_web3_rs::Error> {
+        self.contract.abi().event(event_name).map_err(|e| {
+            ic_web3_rs::Error::Decoder(format!(
+                "event {} not found in contract abi: {}",
+                event_name, e
+            ))
+        })
+    }
+
+    async fn find_logs(
+        &self,
+        parser: &Event,
+        topics: TopicFilter,
+        from: u64,
+        to: u64,
+        call_options: CallOptions,
+    ) -> Result<EventLogs, ic_web3_rs::Error> {
         let logs = self
             .context
             .eth()
@@ -123,149 +147,109 @@ impl LogProvider for Web3Provider {
                     .from_block(BlockNumber::Number(from.into()))
                     .to_block(BlockNumber::Number(to.into()))
                     .address(vec![self.contract.address()])
-                    .topic_filter(TopicFilter {
-                        topic0: Topic::This(event_sig(&self.contract, event_name).unwrap()),
-                        topic1: Topic::Any,
-                        topic2: Topic::Any,
-                        topic3: Topic::Any,
-                    })
+                    .topic_filter(topics)
                     .build(),
                 call_options,
             )
-            .await?
-            .into_iter()
-            .filter(|log| !log.removed.unwrap_or_default())
-            .filter(|log| log.transaction_index.is_some())
-            .filter(|log| log.block_hash.is_some())
-            .map(|log| EventLog {
-                event: parser
-                    .parse_log(RawLog {
-                        data: log.data.0.clone(),
-                        topics: log.topics.clone(),
-                    })
-                    .unwrap(),
-                log,
-            })
-            .fold(HashMap::new(), |mut acc, event| {
-                let block = event.log.block_number.unwrap().as_u64();
-                let events = acc.entry(block).or_insert_with(Vec::new);
-                events.push(event);
-                acc
-            });
-        Ok(logs)
+            .await?;
+        Ok(decode_logs(parser, logs))
+    }
+}
+
+/// Groups the logs of `parser` by block, skipping removed and pending logs.
+fn decode_logs(parser: &Event, logs: Vec<EthLog>) -> EventLogs {
+    logs.into_iter()
+        .filter(|log| !log.removed.unwrap_or_default())
+        .filter(|log| log.transaction_index.is_some())
+        .filter(|log| log.block_hash.is_some())
+        // Without a signature topic, any other event emitted by the
+        // contract also matches the filter. Those are not decode errors,
+        // they are simply not the event we are looking for.
+        .filter(|log| !parser.anonymous || matches_shape(parser, log))
+        .fold(EventLogs::default(), |mut acc, log| {
+            let block = match log.block_number {
+                Some(block) => block.as_u64(),
+                None => {
+                    acc.errors.push(LogDecodeError {
+                        kind: LogDecodeErrorKind::MissingBlockNumber,
+                        log,
+                    });
+                    return acc;
+                }
+            };
+            match parser.parse_log(raw_log(&log)) {
+                Ok(event) => acc
+                    .events
+                    .entry(block)
+                    .or_default()
+                    .push(EventLog { event, log }),
+                Err(e) => acc.errors.push(LogDecodeError {
+                    kind: LogDecodeErrorKind::Abi(e.to_string()),
+                    log,
+                }),
+            }
+            acc
+        })
+}
+
+fn raw_log(log: &EthLog) -> RawLog {
+    RawLog {
+        data: log.data.0.clone(),
+        topics: log.topics.clone(),
     }
 }
 
+/// Whether the log has exactly the layout of the event: one topic per indexed
+/// input, and data that is exactly the encoding of the other inputs.
+fn matches_shape(event: &Event, log: &EthLog) -> bool {
+    let decoded = match event.parse_log(raw_log(log)) {
+        Ok(decoded) => decoded,
+        Err(_) => return false,
+    };
+    let data = event
+        .inputs
+        .iter()
+        .zip(decoded.params)
+        .filter(|(input, _)| !input.indexed)
+        .map(|(_, param)| param.value)
+        .collect::<Vec<_>>();
+    ic_web3_rs::ethabi::encode(&data) == log.data.0
+}
+
 impl Web3Provider {
-    pub async fn build_eip_1559_tx_params(&self) -> Result<Options, ic_web3_rs::Error> {
-        let eth = self.context.eth();
-        let current_block = self
-            .with_retry(|| eth.block(BlockId::Number(BlockNumber::Latest), CallOptions::default()))
-            .await?;
-        if current_block.is_none() {
-            return Err(ic_web3_rs::Error::InvalidResponse(
-                "No block returned".to_string(),
-            ));
+    /// The context the fee and nonce parameters are built with, which retries
+    /// as often as this provider.
+    fn fee_context(&self) -> Web3Context {
+        if self.rpc_call_max_retry == self.context.max_retry() {
+            self.context.clone()
+        } else {
+            self.context.clone().with_max_retry(self.rpc_call_max_retry)
         }
-        let current_block = current_block.unwrap();
-        self._build_eip_1559_tx_params(current_block.base_fee_per_gas.unwrap_or_default())
-            .await
+    }
+
+    pub async fn build_eip_1559_tx_params(&self) -> Result<Options, ic_web3_rs::Error> {
+        self.fee_context().build_eip_1559_tx_params().await
     }
 
     pub async fn build_eip_1559_tx_params_with_fee_history(
         &self,
     ) -> Result<Options, ic_web3_rs::Error> {
-        let eth = self.context.eth();
-        let fee_history = self
-            .with_retry(|| {
-                eth.fee_history(
-                    U256::one(),
-                    BlockNumber::Latest,
-                    None,
-                    CallOptions::default(),
-                )
-            })
-            .await?;
-        self._build_eip_1559_tx_params(
-            fee_history
-                .base_fee_per_gas
-                .get(0)
-                .map(|f| *f)
-                .unwrap_or_default(),
-        )
-        .await
+        self.fee_context()
+            .build_eip_1559_tx_params_with_fee_history()
+            .await
     }
 
     pub async fn build_eip_1559_tx_params_with_batch(&self) -> Result<Options, ic_web3_rs::Error> {
-        let requests = vec![
-            EVMRpcMethod::FeeHistory(U256::one(), BlockNumber::Latest, None),
-            EVMRpcMethod::MaxPriorityFeePerGas,
-            EVMRpcMethod::TransactionCount(self.context.from(), BlockNumber::Latest),
-        ];
-        let resp = self.batch_call(&requests).await?;
-
-        let (ok, err) = resp.into_iter().partition::<Vec<_>, _>(Result::is_ok);
-        if !err.is_empty() {
-            return Err(ic_web3_rs::error::Error::InvalidResponse(format!(
-                "Some method failed: {err:?}"
-            )));
-        }
-        if ok.len() != requests.len() {
-            return Err(ic_web3_rs::error::Error::InvalidResponse(format!(
-                "Some method not responded. response={ok:?}"
-            )));
-        }
-
-        let mut ok = ok.into_iter().filter_map(Result::ok).collect::<Vec<_>>();
-        let fee_history: FeeHistory = serde_json::from_value(ok.remove(0))?;
-        let base_fee_per_gas = fee_history
-            .base_fee_per_gas
-            .get(0)
-            .map(|f| *f)
-            .unwrap_or_default();
-        let max_priority_fee_per_gas: U256 = serde_json::from_value(ok.remove(0))?;
-        let nonce = serde_json::from_value(ok.remove(0))?;
-
-        Ok(Options {
-            max_fee_per_gas: Some(calc_max_fee_per_gas(
-                max_priority_fee_per_gas,
-                base_fee_per_gas,
-            )),
-            max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
-            nonce: Some(nonce),
-            transaction_type: Some(U64::from(2)), // EIP1559_TX_ID for default
-            ..Default::default()
-        })
+        self.context.build_eip_1559_tx_params_with_batch().await
     }
 
     pub async fn build_legacy_tx_params_with_batch(&self) -> Result<Options, ic_web3_rs::Error> {
-        let requests = vec![
-            EVMRpcMethod::GasPrice,
-            EVMRpcMethod::TransactionCount(self.context.from(), BlockNumber::Latest),
-        ];
-        let resp = self.batch_call(&requests).await?;
-
-        let (ok, err) = resp.into_iter().partition::<Vec<_>, _>(Result::is_ok);
-        if !err.is_empty() {
-            return Err(ic_web3_rs::error::Error::InvalidResponse(format!(
-                "Some method failed: {err:?}"
-            )));
-        }
-        if ok.len() != requests.len() {
-            return Err(ic_web3_rs::error::Error::InvalidResponse(format!(
-                "Some method not responded. response={ok:?}"
-            )));
-        }
-
-        let mut ok = ok.into_iter().filter_map(Result::ok).collect::<Vec<_>>();
-        let gas_price: U256 = serde_json::from_value(ok.remove(0))?;
-        let nonce = serde_json::from_value(ok.remove(0))?;
+        self.context.build_legacy_tx_params_with_batch().await
+    }
 
-        Ok(Options {
-            gas_price: Some(gas_price),
-            nonce: Some(nonce),
-            ..Default::default()
-        })
+    /// EIP-1559 params on chains which support them, legacy params otherwise.
+    pub async fn build_default_tx_params(&self) -> Result<Options, ic_web3_rs::Error> {
+        self.fee_context().build_default_tx_params().await
     }
 
     pub async fn estimate_gas<P>(
@@ -285,46 +269,12 @@ impl Web3Provider {
 
     pub async fn batch_call(
         &self,
-        calls: &Vec<EVMRpcMethod>,
+        calls: &[EVMRpcMethod],
     ) -> Result<Vec<Result<serde_json::Value, ic_web3_rs::Error>>, ic_web3_rs::Error> {
-        let transport = self.context.eth().transport();
-        let calls = calls
-            .into_iter()
-            .map(|c| transport.prepare(c.method(), c.params()))
-            .collect::<Vec<_>>();
-
-        transport.send_batch(calls).await
-    }
-
-    async fn _build_eip_1559_tx_params(
-        &self,
-        base_fee_per_gas: U256,
-    ) -> Result<Options, ic_web3_rs::Error> {
-        let eth = self.context.eth();
-        let max_priority_fee_per_gas = self
-            .with_retry(|| eth.max_priority_fee_per_gas(CallOptions::default()))
-            .await?;
-        let nonce = self
-            .with_retry(|| eth.transaction_count(self.context.from(), None, CallOptions::default()))
-            .await?;
-
-        Ok(Options {
-            max_fee_per_gas: Some(calc_max_fee_per_gas(
-                max_priority_fee_per_gas,
-                base_fee_per_gas,
-            )),
-            max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
-            nonce: Some(nonce),
-            transaction_type: Some(U64::from(2)), // EIP1559_TX_ID for default
-            ..Default::default()
-        })
+        self.context.batch_call(calls).await
     }
 }
 
-fn calc_max_fee_per_gas(max_priority_fee_per_gas: U256, base_fee_per_gas: U256) -> U256 {
-    max_priority_fee_per_gas + (base_fee_per_gas * U256::from(2))
-}
-
 #[async_trait]
 impl SendProvider for Web3Provider {
     type Out = (H256, Option<ic_web3_rs::Error>);
@@ -334,37 +284,59 @@ impl SendProvider for Web3Provider {
         params: Params,
         options: Option<Options>,
     ) -> Result<Self::Out, ic_web3_rs::Error> {
-        let canister_addr = self.context.from();
-        let call_option = match options {
-            Some(options) => options,
-            None => self.build_eip_1559_tx_params().await?,
-        };
+        if self.context.is_dry_run() {
+            // A revert is part of the outcome, like a failed broadcast is.
+            let options = self.fee_context().tx_options(options).await?;
+            let data = self.encode_input(func, params)?;
+            let to = self.contract.address();
+            return Ok(self.context.dry_run(to, data, options).await);
+        }
+        let send_option = options.as_ref().and_then(|o| o.call_options.clone());
+        let signed_tx = self.sign(func, params, options).await?;
+        let res = self
+            .context
+            .send_raw_transaction(
+                &signed_tx,
+                send_option.unwrap_or_else(|| self.context.call_options()),
+            )
+            .await;
+        Ok((signed_tx.transaction_hash, res.err()))
+    }
+}
 
-        let send_option = call_option.call_options;
-        let signed_tx = self
-            .contract
-            .sign(
-                func,
-                params,
+impl Web3Provider {
+    fn encode_input<Params: Tokenize>(
+        &self,
+        func: &str,
+        params: Params,
+    ) -> Result<Vec<u8>, ic_web3_rs::Error> {
+        self.contract
+            .abi()
+            .function(func)
+            .and_then(|function| function.encode_input(&params.into_tokens()))
+            .map_err(|err| ic_web3_rs::Error::Decoder(format!("{:?}", err)))
+    }
+
+    /// Signs a call to `func` like `send` does, but returns the signed
+    /// transaction instead of broadcasting it.
+    pub async fn sign<Params: Tokenize + Send>(
+        &self,
+        func: &str,
+        params: Params,
+        options: Option<Options>,
+    ) -> Result<SignedTransaction, ic_web3_rs::Error> {
+        let call_option = self.fee_context().tx_options(options).await?;
+        let data = self.encode_input(func, params)?;
+        self.context
+            .sign_transaction(
+                self.contract.address(),
+                data,
                 Options {
                     call_options: None,
                     ..call_option
                 },
-                hex::encode(canister_addr),
-                KeyInfo {
-                    derivation_path: vec![default_derivation_key()],
-                    key_name: self.context.key_name().to_string(),
-                    ecdsa_sign_cycles: None, // use default (is there a problem with prod_key?)
-                },
-                self.context.chain_id(),
             )
-            .await?;
-        let res = self
-            .context
-            .eth()
-            .send_raw_transaction(signed_tx.raw_transaction, send_option.unwrap_or_default())
-            .await;
-        Ok((signed_tx.transaction_hash, res.err()))
+            .await
     }
 }
 
@@ -379,11 +351,77 @@ impl Web3Provider {
 
         Self {
             contract,
+            rpc_call_max_retry: context.max_retry(),
             context,
-            rpc_call_max_retry: RPC_CALL_MAX_RETRY,
         }
     }
     pub fn set_max_retry(&mut self, max_retry: u8) {
         self.rpc_call_max_retry = max_retry;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_web3_rs::ethabi::{EventParam, ParamType, Token};
+    use ic_web3_rs::types::Bytes;
+
+    fn log(topics: Vec<H256>, data: Vec<u8>) -> EthLog {
+        EthLog {
+            address: Address::zero(),
+            topics,
+            data: Bytes(data),
+            block_hash: Some(H256::zero()),
+            block_number: Some(7.into()),
+            transaction_hash: Some(H256::zero()),
+            transaction_index: Some(0.into()),
+            log_index: Some(0.into()),
+            transaction_log_index: None,
+            log_type: None,
+            removed: Some(false),
+        }
+    }
+
+    #[test]
+    fn anonymous_logs_are_matched_by_shape() {
+        // event Deposit(address indexed from, uint256 amount) anonymous;
+        let event = Event {
+            name: "Deposit".to_string(),
+            inputs: vec![
+                EventParam {
+                    name: "from".to_string(),
+                    kind: ParamType::Address,
+                    indexed: true,
+                },
+                EventParam {
+                    name: "amount".to_string(),
+                    kind: ParamType::Uint(256),
+                    indexed: false,
+                },
+            ],
+            anonymous: true,
+        };
+        let from = H256::from(Address::repeat_byte(1));
+        let amount = ic_web3_rs::ethabi::encode(&[Token::Uint(5.into())]);
+        let logs = vec![
+            log(vec![from], amount.clone()),
+            // A regular event of the same contract, with its signature topic.
+            log(vec![H256::repeat_byte(9), from], amount.clone()),
+            // The same topics, but two words of data.
+            log(vec![from], [amount.clone(), amount].concat()),
+            log(vec![], vec![]),
+        ];
+
+        let decoded = decode_logs(&event, logs);
+
+        assert!(decoded.errors.is_empty());
+        assert_eq!(decoded.events.len(), 1);
+        let events = &decoded.events[&7];
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].event.params[0].value,
+            Token::Address(Address::repeat_byte(1))
+        );
+        assert_eq!(events[0].event.params[1].value, Token::Uint(5.into()));
+    }
+}
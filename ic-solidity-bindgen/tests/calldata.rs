@@ -0,0 +1,79 @@
+use ic_solidity_bindgen::{contract_abi, types::EventLogs, LogProvider};
+use ic_web3_rs::{
+    ethabi::{self, Token, TopicFilter},
+    transports::ic_http_client::CallOptions,
+    types::{Address, U256},
+};
+
+contract_abi!("ic-solidity-bindgen/tests/abis/Router.json");
+
+fn abi() -> ethabi::Contract {
+    ethabi::Contract::load(&include_bytes!("abis/Router.json")[..]).unwrap()
+}
+
+#[test]
+fn struct_input_round_trips() {
+    let path = vec![1, 2, 3];
+    let recipient = Address::repeat_byte(0x11);
+    let amount_in = U256::from(1_000);
+    let expected = abi()
+        .function("exactInput")
+        .unwrap()
+        .encode_input(&[Token::Tuple(vec![
+            Token::Bytes(path.clone()),
+            Token::Address(recipient),
+            Token::Uint(amount_in),
+        ])])
+        .unwrap();
+
+    let calldata = RouterCall::encode_exact_input((path.clone(), recipient, amount_in)).unwrap();
+    assert_eq!(calldata, expected);
+    assert_eq!(
+        RouterCall::decode(&expected).unwrap(),
+        RouterCall::ExactInput {
+            params: (path, recipient, amount_in)
+        }
+    );
+}
+
+#[test]
+fn inputs_round_trip() {
+    let to = Address::repeat_byte(0x22);
+    let amount = U256::from(7);
+    let expected = abi()
+        .function("refund")
+        .unwrap()
+        .encode_input(&[Token::Address(to), Token::Uint(amount)])
+        .unwrap();
+
+    let call = RouterCall::Refund { to, amount };
+    assert_eq!(call.encode().unwrap(), expected);
+    assert_eq!(RouterCall::decode(&expected).unwrap(), call);
+    assert_eq!(RouterCall::REFUND_SELECTOR, expected[..4]);
+
+    let owner = RouterCall::encode_owner().unwrap();
+    assert_eq!(RouterCall::decode(&owner).unwrap(), RouterCall::Owner);
+}
+
+#[test]
+fn decode_rejects_unknown_and_short_calldata() {
+    assert!(RouterCall::decode(&[0x12, 0x34]).is_err());
+    assert!(RouterCall::decode(&[0xde, 0xad, 0xbe, 0xef]).is_err());
+    let mut truncated = RouterCall::Refund {
+        to: Address::zero(),
+        amount: U256::one(),
+    }
+    .encode()
+    .unwrap();
+    truncated.truncate(20);
+    assert!(RouterCall::decode(&truncated).is_err());
+}
+
+// Only has to compile: the bindings of an anonymous event must not ask more
+// of the provider than `LogProvider`.
+#[allow(dead_code)]
+async fn find_swept<P: LogProvider>(router: &Router<P>) -> Result<EventLogs, ic_web3_rs::Error> {
+    router
+        .event_swept(0, 100, TopicFilter::default(), CallOptions::default())
+        .await
+}